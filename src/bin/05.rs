@@ -3,8 +3,10 @@ use lazy_static::lazy_static;
 use regex::Regex;
 
 use std::collections::VecDeque;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::iter::once;
 
 struct MoveInstruction {
     from: usize,
@@ -12,31 +14,213 @@ struct MoveInstruction {
     amount: usize,
 }
 
+#[derive(Debug)]
+enum CraneError {
+    NoSuchStack { step: usize, stack: usize },
+    StackUnderflow { step: usize, stack: usize, available: usize, requested: usize },
+    EmptyStack { stack: usize },
+    ZeroBatchSize,
+}
+
+impl fmt::Display for CraneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CraneError::NoSuchStack { step, stack } =>
+                write!(f, "step {}: there is no stack {}", step, stack),
+            CraneError::StackUnderflow { step, stack, available, requested } =>
+                write!(f, "step {}: cannot move {} crates from stack {}, which only holds {}", step, requested, stack, available),
+            CraneError::EmptyStack { stack } =>
+                write!(f, "stack {} has no crate on top", stack),
+            CraneError::ZeroBatchSize =>
+                write!(f, "a batch crane must lift at least one crate at a time"),
+        }
+    }
+}
+
+trait Crane {
+    fn move_crates(&self, from: &mut VecDeque<String>, to: &mut VecDeque<String>, amount: usize);
+
+    fn apply(&self, crates: &mut [VecDeque<String>], instruction: &MoveInstruction, step: usize) -> Result<(), CraneError> {
+        validate_instruction(crates, instruction, step)?;
+        let (from, to) = (instruction.from - 1, instruction.to - 1);
+        if from == to { return Ok(()); }
+
+        let (from_stack, to_stack) = if from < to {
+            let (low, high) = crates.split_at_mut(to);
+            (&mut low[from], &mut high[0])
+        } else {
+            let (low, high) = crates.split_at_mut(from);
+            (&mut high[0], &mut low[to])
+        };
+        self.move_crates(from_stack, to_stack, instruction.amount);
+        Ok(())
+    }
+}
+
+struct CrateMover9000;
+
+impl Crane for CrateMover9000 {
+    fn move_crates(&self, from: &mut VecDeque<String>, to: &mut VecDeque<String>, amount: usize) {
+        for _ in 0..amount {
+            to.push_back(from.pop_back().expect("tried to pop from empty stack"));
+        }
+    }
+}
+
+struct CrateMover9001;
+
+impl Crane for CrateMover9001 {
+    fn move_crates(&self, from: &mut VecDeque<String>, to: &mut VecDeque<String>, amount: usize) {
+        let mut moved_crates = from.split_off(from.len() - amount);
+        to.append(&mut moved_crates);
+    }
+}
+
+#[allow(dead_code)]
+struct BatchCrateMover {
+    batch_size: usize,
+}
+
+#[allow(dead_code)]
+impl BatchCrateMover {
+
+    fn new(batch_size: usize) -> Result<Self, CraneError> {
+        if batch_size == 0 { return Err(CraneError::ZeroBatchSize); }
+        Ok(Self { batch_size })
+    }
+}
+
+impl Crane for BatchCrateMover {
+    fn move_crates(&self, from: &mut VecDeque<String>, to: &mut VecDeque<String>, amount: usize) {
+        let mut remaining = amount;
+        while remaining > 0 {
+            let batch = remaining.min(self.batch_size);
+            let mut moved_crates = from.split_off(from.len() - batch);
+            to.append(&mut moved_crates);
+            remaining -= batch;
+        }
+    }
+}
+
+struct AppliedMove {
+    from: usize,
+    to: usize,
+    lifted: Vec<String>,
+}
+
+struct Replay<'a, C: Crane> {
+    crane: &'a C,
+    instructions: &'a [MoveInstruction],
+    crates: Vec<VecDeque<String>>,
+    history: Vec<AppliedMove>,
+}
+
+#[allow(dead_code)]
+impl<'a, C: Crane> Replay<'a, C> {
+
+    fn new(crane: &'a C, crates: &[VecDeque<String>], instructions: &'a [MoveInstruction]) -> Self {
+        Self { crane, instructions, crates: crates.to_vec(), history: Vec::new() }
+    }
+
+    fn position(&self) -> usize {
+        self.history.len()
+    }
+
+    fn crates(&self) -> &[VecDeque<String>] {
+        &self.crates
+    }
+
+    fn step_forward(&mut self) -> Result<bool, CraneError> {
+        let step = self.position() + 1;
+        let instruction = match self.instructions.get(step - 1) {
+            Some(instruction) => instruction,
+            None => return Ok(false),
+        };
+        validate_instruction(&self.crates, instruction, step)?;
+        let from_stack = &self.crates[instruction.from - 1];
+        let lifted = from_stack.range(from_stack.len() - instruction.amount..).cloned().collect();
+        self.crane.apply(&mut self.crates, instruction, step)?;
+        self.history.push(AppliedMove { from: instruction.from - 1, to: instruction.to - 1, lifted });
+        Ok(true)
+    }
+
+    fn step_back(&mut self) -> bool {
+        match self.history.pop() {
+            Some(applied) => {
+                if applied.from != applied.to {
+                    let to_stack = &mut self.crates[applied.to];
+                    to_stack.truncate(to_stack.len() - applied.lifted.len());
+                    self.crates[applied.from].extend(applied.lifted);
+                }
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn seek(&mut self, step: usize) -> Result<(), CraneError> {
+        while self.position() > step { self.step_back(); }
+        while self.position() < step {
+            if !self.step_forward()? { break; }
+        }
+        Ok(())
+    }
+
+    fn last_touched(&self, stack: usize) -> Option<usize> {
+        self.history.iter().rposition(|applied| applied.from + 1 == stack || applied.to + 1 == stack).map(|idx| idx + 1)
+    }
+
+    fn first_step_with_top(&mut self, top: &str) -> Result<Option<usize>, CraneError> {
+        self.seek(0)?;
+        while self.step_forward()? {
+            if top_crates(&self.crates).is_ok_and(|crates| crates == top) { return Ok(Some(self.position())); }
+        }
+        Ok(None)
+    }
+}
+
 fn read_lines() -> impl Iterator<Item = String> {
     let file = File::open("inputs/05.txt").expect("input file not present");
     BufReader::new(file).lines().map(|l| l.expect("error reading from file"))
 }
 
-fn parse_crate(s: &str) -> Option<char> {
-    match &s[0..1] {
-        "[" => s[1..2].chars().next(),
-        _ => None
+fn parse_crates<I: Iterator<Item = String>>(crate_lines: I) -> Vec<VecDeque<String>> {
+    lazy_static! {
+        static ref STACK_NUMBER_REGEX: Regex = Regex::new(r"\d+").unwrap();
+        static ref CRATE_REGEX: Regex = Regex::new(r"\[(?P<label>[^\]]*)\]").unwrap();
     }
-}
-
-fn parse_crates<I: Iterator<Item = String>>(crate_lines: I) -> Vec<VecDeque<char>> {
-    let mut stacks = Vec::new();
+    let lines: Vec<_> = crate_lines.collect();
+    let (base_line, crate_rows) = lines.split_last().expect("no crate drawing in input");
+    // Columns are kept doubled so that the centre of a match stays an integer.
+    let columns: Vec<_> = STACK_NUMBER_REGEX.find_iter(base_line).map(|m| m.start() + m.end()).collect();
 
-    for line in crate_lines {
-        for idx in 0..=line.len() / 4 {
-            if idx >= stacks.len() { stacks.push(VecDeque::new()); }
-            if let Some(c) = parse_crate(&line[4 * idx .. 4 * idx + 3]) { stacks[idx].push_front(c); }
+    let mut stacks = vec![VecDeque::new(); columns.len()];
+    for line in crate_rows {
+        for captures in CRATE_REGEX.captures_iter(line) {
+            let center = captures.get(0).map(|m| m.start() + m.end()).unwrap();
+            let nearest = columns.iter().position_min_by_key(|column| column.abs_diff(center));
+            if let Some(idx) = nearest { stacks[idx].push_front(captures["label"].to_string()); }
         }
     }
 
     stacks
 }
 
+#[allow(dead_code)]
+fn render_crates(crates: &[VecDeque<String>]) -> String {
+    let height = crates.iter().map(|stack| stack.len()).max().unwrap_or(0);
+    let cell_width = crates.iter().flatten().map(|label| label.len() + 2)
+        .chain(once(crates.len().to_string().len()))
+        .max().unwrap();
+
+    let crate_rows = (0..height).rev().map(|level| crates.iter()
+        .map(|stack| format!("{:<width$}", stack.get(level).map(|label| format!("[{}]", label)).unwrap_or_default(), width = cell_width))
+        .join(" "));
+    let base_line = (1..=crates.len()).map(|idx| format!("{:^width$}", idx, width = cell_width)).join(" ");
+
+    crate_rows.chain(once(base_line)).join("\n")
+}
+
 fn parse_move_instruction(instruction: &str) -> MoveInstruction {
     lazy_static! {
         static ref INSTRUCTION_REGEX: Regex = Regex::new(r"move (?P<amount>\d+) from (?P<from>\d+) to (?P<to>\d+)").unwrap();
@@ -48,7 +232,7 @@ fn parse_move_instruction(instruction: &str) -> MoveInstruction {
     }).expect("failed to parse instruction")
 }
 
-fn parse_input<I: Iterator<Item = String>>(lines: I) -> (Vec<VecDeque<char>>, Vec<MoveInstruction>) {
+fn parse_input<I: Iterator<Item = String>>(lines: I) -> (Vec<VecDeque<String>>, Vec<MoveInstruction>) {
     let line_groups = lines.group_by(|line| line.is_empty());
     let (crate_lines, instruction_lines) = line_groups.into_iter()
         .filter_map(|(k, g)| if k { None } else { Some(g)})
@@ -58,26 +242,93 @@ fn parse_input<I: Iterator<Item = String>>(lines: I) -> (Vec<VecDeque<char>>, Ve
     (parse_crates(crate_lines), instruction_lines.map(|s| parse_move_instruction(&s)).collect())
 }
 
-fn apply_instruction_part_1(crates: &mut[VecDeque<char>], instruction: &MoveInstruction) {
-    for _ in 0..instruction.amount {
-        let crate_to_move = crates[instruction.from - 1].pop_back().expect("tried to pop from empty stack");
-        crates[instruction.to - 1].push_back(crate_to_move);
+fn validate_instruction(crates: &[VecDeque<String>], instruction: &MoveInstruction, step: usize) -> Result<(), CraneError> {
+    for stack in [instruction.from, instruction.to] {
+        if stack == 0 || stack > crates.len() { return Err(CraneError::NoSuchStack { step, stack }); }
     }
+
+    let available = crates[instruction.from - 1].len();
+    if instruction.amount > available {
+        return Err(CraneError::StackUnderflow { step, stack: instruction.from, available, requested: instruction.amount });
+    }
+
+    Ok(())
+}
+
+fn rearrange<C: Crane>(crane: &C, crates: &[VecDeque<String>], instructions: &[MoveInstruction]) -> Result<Vec<VecDeque<String>>, CraneError> {
+    let mut crates = crates.to_vec();
+    for (idx, instruction) in instructions.iter().enumerate() {
+        crane.apply(&mut crates, instruction, idx + 1)?;
+    }
+    Ok(crates)
 }
 
-fn apply_instruction_part_2(crates: &mut[VecDeque<char>], instruction: &MoveInstruction) {
-    let mut moved_crates = crates[instruction.from - 1].split_off(crates[instruction.from - 1].len() - instruction.amount);
-    crates[instruction.to - 1].append(&mut moved_crates);
+fn top_crates(crates: &[VecDeque<String>]) -> Result<String, CraneError> {
+    crates.iter().enumerate()
+        .map(|(idx, stack)| stack.back().map(String::as_str).ok_or(CraneError::EmptyStack { stack: idx + 1 }))
+        .collect()
 }
 
 fn main() {
     let (crates, instructions) = parse_input(read_lines());
-    
-    let mut crates_part_1 = crates.clone();
-    for instruction in &instructions { apply_instruction_part_1(&mut crates_part_1, instruction); }
-    println!("Part 1: {}", crates_part_1.iter().map(|stack| stack.back().expect("empty stack")).join(""));
-
-    let mut crates_part_2 = crates.clone();
-    for instruction in &instructions { apply_instruction_part_2(&mut crates_part_2, instruction); }
-    println!("Part 2: {}", crates_part_2.iter().map(|stack| stack.back().expect("empty stack")).join(""));
+
+    let crates_part_1 = rearrange(&CrateMover9000, &crates, &instructions).unwrap_or_else(|err| panic!("{}", err));
+    println!("Part 1: {}", top_crates(&crates_part_1).unwrap_or_else(|err| panic!("{}", err)));
+
+    let crates_part_2 = rearrange(&CrateMover9001, &crates, &instructions).unwrap_or_else(|err| panic!("{}", err));
+    println!("Part 2: {}", top_crates(&crates_part_2).unwrap_or_else(|err| panic!("{}", err)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DRAWING: &str = "    [D]    \n[N] [C]    \n[Z] [M] [P]\n 1   2   3 ";
+
+    #[test]
+    fn render_round_trips_parsed_crates() {
+        let crates = parse_crates(DRAWING.lines().map(str::to_string));
+        assert_eq!(render_crates(&crates), DRAWING);
+
+        let crates = vec![VecDeque::from(["C".to_string()]), VecDeque::from(["DEF".to_string(), "AB".to_string()])];
+        let drawing = render_crates(&crates);
+        assert_eq!(parse_crates(drawing.lines().map(str::to_string)), crates);
+
+        let crates = vec![VecDeque::from(["ABCDE".to_string()]), VecDeque::new(), VecDeque::from(["Z".to_string()])];
+        let drawing = render_crates(&crates);
+        assert_eq!(parse_crates(drawing.lines().map(|line| line.trim_end().to_string())), crates);
+    }
+
+    fn check_replay<C: Crane>(crane: &C, crates: &[VecDeque<String>], instructions: &[MoveInstruction]) {
+        let mut replay = Replay::new(crane, crates, instructions);
+        replay.seek(instructions.len()).unwrap();
+        assert_eq!(replay.crates(), rearrange(crane, crates, instructions).unwrap());
+        replay.seek(2).unwrap();
+        assert_eq!(replay.crates(), rearrange(crane, crates, &instructions[..2]).unwrap());
+        replay.seek(0).unwrap();
+        assert_eq!(replay.crates(), crates);
+    }
+
+    #[test]
+    fn replay_steps_match_rearrange_and_undo_exactly() {
+        let crates = parse_crates(DRAWING.lines().map(str::to_string));
+        let instructions: Vec<_> = ["move 1 from 2 to 1", "move 3 from 1 to 3", "move 2 from 2 to 1", "move 1 from 1 to 2"]
+            .into_iter().map(parse_move_instruction).collect();
+
+        check_replay(&CrateMover9000, &crates, &instructions);
+        check_replay(&CrateMover9001, &crates, &instructions);
+        check_replay(&BatchCrateMover::new(2).unwrap(), &crates, &instructions);
+        assert!(matches!(BatchCrateMover::new(0), Err(CraneError::ZeroBatchSize)));
+
+        let mut replay = Replay::new(&CrateMover9000, &crates, &instructions);
+        assert_eq!(replay.first_step_with_top("CMZ").unwrap(), Some(4));
+        assert_eq!(replay.last_touched(3), Some(2));
+        replay.seek(2).unwrap();
+        assert!(matches!(top_crates(replay.crates()), Err(CraneError::EmptyStack { stack: 1 })));
+
+        let invalid = [parse_move_instruction("move 4 from 2 to 1")];
+        let mut replay = Replay::new(&CrateMover9000, &crates, &invalid);
+        assert!(matches!(replay.step_forward(), Err(CraneError::StackUnderflow { step: 1, stack: 2, available: 3, requested: 4 })));
+        assert_eq!(replay.crates(), crates);
+    }
 }
@@ -1,17 +1,58 @@
-use itertools::Itertools;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, Read};
 
-use std::fs;
+struct DistinctWindow {
+    size: usize,
+    counts: [usize; 256],
+    distinct: usize,
+}
+
+impl DistinctWindow {
+
+    fn new(size: usize) -> Self {
+        Self { size, counts: [0; 256], distinct: 0 }
+    }
+
+    fn push(&mut self, byte: u8, evicted: Option<u8>) -> bool {
+        self.counts[byte as usize] += 1;
+        if self.counts[byte as usize] == 1 { self.distinct += 1; }
+
+        if let Some(old) = evicted {
+            self.counts[old as usize] -= 1;
+            if self.counts[old as usize] == 0 { self.distinct -= 1; }
+        }
 
-fn read_data() -> String {
-    fs::read_to_string("inputs/06.txt").expect("unable to read input file")
+        self.distinct == self.size
+    }
 }
 
-fn characters_until_n_distinct(data: &str, n: usize) -> usize {
-    data.chars().collect_vec().windows(n).position(|s| s.iter().all_unique()).expect("not found") + n
+fn read_data() -> File {
+    File::open("inputs/06.txt").expect("input file not present")
+}
+
+fn marker_positions<R: Read>(reader: R, sizes: &[usize]) -> Vec<Vec<usize>> {
+    let mut windows: Vec<_> = sizes.iter().map(|&size| DistinctWindow::new(size)).collect();
+    let mut positions = vec![Vec::new(); sizes.len()];
+    let mut history = VecDeque::new();
+    let history_length = sizes.iter().copied().max().unwrap_or(0);
+
+    let bytes = BufReader::new(reader).bytes().map(|b| b.expect("error reading from input")).filter(|&b| b != b'\n' && b != b'\r');
+    for (idx, byte) in bytes.enumerate() {
+        history.push_back(byte);
+        if history.len() > history_length + 1 { history.pop_front(); }
+
+        for (window, found) in windows.iter_mut().zip(positions.iter_mut()) {
+            let evicted = history.len().checked_sub(window.size + 1).map(|i| history[i]);
+            if window.push(byte, evicted) { found.push(idx + 1); }
+        }
+    }
+
+    positions
 }
 
 fn main() {
-    let data = read_data();
-    println!("Part 1: {}", characters_until_n_distinct(&data, 4));
-    println!("Part 2: {}", characters_until_n_distinct(&data, 14));
+    let positions = marker_positions(read_data(), &[4, 14]);
+    println!("Part 1: {}", positions[0].first().expect("not found"));
+    println!("Part 2: {}", positions[1].first().expect("not found"));
 }
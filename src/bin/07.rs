@@ -1,24 +1,34 @@
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    character::complete::{digit1, line_ending, not_line_ending, space1},
-    combinator::{map, map_res, opt, recognize, value},
-    multi::many0,
-    sequence::{preceded, terminated, tuple},
+    bytes::complete::{escaped_transform, tag, take_while_m_n},
+    character::complete::{char, digit1, multispace0, none_of, not_line_ending, space1},
+    combinator::{all_consuming, map, map_opt, map_res, opt, recognize, value},
+    multi::separated_list0,
+    sequence::{delimited, preceded, separated_pair, tuple},
     IResult, Parser, error::ParseError,
 };
 
-use std::collections::HashMap;
+use itertools::Itertools;
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt;
 use std::fs;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 enum Node {
     File(usize),
     Directory(usize, HashMap<String, Node>),
 }
 
+#[derive(Clone, Copy)]
+enum SortOrder {
+    Name,
+    Size,
+}
+
 struct NodeIterator<'a> {
-    node_stack: Vec<&'a Node>,
+    node_stack: Vec<(String, &'a Node)>,
 }
 
 impl Node {
@@ -34,33 +44,270 @@ impl Node {
         matches!(self, Node::Directory(_, _))
     }
 
-    fn iter(&self) -> NodeIterator {
+    fn iter(&self) -> NodeIterator<'_> {
         NodeIterator::new(self)
     }
+
+    fn find(&self, path: &[String]) -> Option<&Node> {
+        path.iter().try_fold(self, |node, name| match node {
+            Node::Directory(_, contents) => contents.get(name),
+            Node::File(_) => None,
+        })
+    }
+
+    fn find_mut(&mut self, path: &[String]) -> Option<&mut Node> {
+        path.iter().try_fold(self, |node, name| match node {
+            Node::Directory(_, contents) => contents.get_mut(name),
+            Node::File(_) => None,
+        })
+    }
+
+    fn update_sizes(&mut self) -> usize {
+        match self {
+            Node::File(size) => *size,
+            Node::Directory(size, contents) => {
+                *size = contents.values_mut().map(|node| node.update_sizes()).sum();
+                *size
+            }
+        }
+    }
+
+    fn smallest_directory_freeing(&self, disk_size: usize, required_free: usize) -> Option<(String, usize)> {
+        let to_free = (self.size() + required_free).saturating_sub(disk_size);
+        self.iter()
+            .filter(|(_, node)| node.is_directory() && node.size() >= to_free)
+            .map(|(path, node)| (path, node.size()))
+            .min_by_key(|&(_, size)| size)
+    }
+
+    fn lookup(&self, path: &str) -> Option<&Node> {
+        let components: Vec<_> = path.split('/').filter(|c| !c.is_empty()).map(str::to_string).collect();
+        self.find(&components)
+    }
+
+    fn to_json(&self) -> String {
+        let mut output = String::new();
+        self.write_json("/", 0, &mut output);
+        output
+    }
+
+    fn write_json(&self, name: &str, depth: usize, output: &mut String) {
+        let indent = "  ".repeat(depth);
+        match self {
+            Node::File(size) => output.push_str(&format!("{}{{\"name\": {}, \"size\": {}}}", indent, json_string_literal(name), size)),
+            Node::Directory(size, contents) => {
+                output.push_str(&format!("{}{{\"name\": {}, \"size\": {}, \"children\": [", indent, json_string_literal(name), size));
+                if !contents.is_empty() {
+                    for (idx, (child_name, child)) in sorted_children(contents, SortOrder::Name).into_iter().enumerate() {
+                        output.push_str(if idx == 0 { "\n" } else { ",\n" });
+                        child.write_json(child_name, depth + 1, output);
+                    }
+                    output.push_str(&format!("\n{}", indent));
+                }
+                output.push_str("]}");
+            },
+        }
+    }
+
+    fn from_json(input: &str) -> Result<Node, JsonError> {
+        let (_, document) = all_consuming(json_value)(input).map_err(|_| JsonError::Syntax)?;
+        node_from_json(&document).map(|(_, node)| node)
+    }
+
+    fn render_tree(&self, name: &str, order: SortOrder, max_depth: Option<usize>) -> String {
+        let mut lines = Vec::new();
+        self.render_tree_into(name, 0, order, max_depth, &mut lines);
+        lines.join("\n")
+    }
+
+    fn render_tree_into(&self, name: &str, depth: usize, order: SortOrder, max_depth: Option<usize>, lines: &mut Vec<String>) {
+        let kind = if self.is_directory() { "dir" } else { "file" };
+        lines.push(format!("{}- {} ({}, size={})", "  ".repeat(depth), name, kind, self.size()));
+        if let Node::Directory(_, contents) = self {
+            if max_depth.is_none_or(|max| depth < max) {
+                for (child_name, child) in sorted_children(contents, order) {
+                    child.render_tree_into(child_name, depth + 1, order, max_depth, lines);
+                }
+            }
+        }
+    }
+
+    fn render_du(&self, path: &str, order: SortOrder, max_depth: Option<usize>) -> String {
+        let mut lines = Vec::new();
+        self.render_du_into(path, 0, order, max_depth, &mut lines);
+        lines.join("\n")
+    }
+
+    fn render_du_into(&self, path: &str, depth: usize, order: SortOrder, max_depth: Option<usize>, lines: &mut Vec<String>) {
+        if let Node::Directory(size, contents) = self {
+            if max_depth.is_none_or(|max| depth < max) {
+                for (child_name, child) in sorted_children(contents, order) {
+                    child.render_du_into(&join_path(path, child_name), depth + 1, order, max_depth, lines);
+                }
+            }
+            lines.push(format!("{}\t{}", human_size(*size), path));
+        }
+    }
+}
+
+fn join_path(parent: &str, name: &str) -> String {
+    if parent.ends_with('/') { format!("{}{}", parent, name) } else { format!("{}/{}", parent, name) }
+}
+
+fn sorted_children(contents: &HashMap<String, Node>, order: SortOrder) -> Vec<(&String, &Node)> {
+    match order {
+        SortOrder::Name => contents.iter().sorted_by(|a, b| a.0.cmp(b.0)).collect(),
+        SortOrder::Size => contents.iter().sorted_by(|a, b| b.1.size().cmp(&a.1.size()).then(a.0.cmp(b.0))).collect(),
+    }
+}
+
+fn json_string_literal(s: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"'  => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\t' => literal.push_str("\\t"),
+            c if c.is_control() => literal.push_str(&format!("\\u{:04x}", c as u32)),
+            c    => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+fn node_from_json(value: &Json) -> Result<(String, Node), JsonError> {
+    let fields = match value {
+        Json::Object(fields) => fields,
+        _ => return Err(JsonError::UnexpectedType("node")),
+    };
+    let field = |key: &str| fields.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+
+    let name = match field("name") {
+        Some(Json::String(name)) => name.clone(),
+        Some(_) => return Err(JsonError::UnexpectedType("name")),
+        None => return Err(JsonError::MissingField("name")),
+    };
+    let size = match field("size") {
+        Some(Json::Number(size)) => *size,
+        Some(_) => return Err(JsonError::UnexpectedType("size")),
+        None => return Err(JsonError::MissingField("size")),
+    };
+    let node = match field("children") {
+        None => Node::File(size),
+        Some(Json::Array(children)) => {
+            let mut contents = HashMap::new();
+            for child in children {
+                let (child_name, child_node) = node_from_json(child)?;
+                if contents.contains_key(&child_name) { return Err(JsonError::DuplicateName(child_name)); }
+                contents.insert(child_name, child_node);
+            }
+            let actual = contents.values().map(Node::size).sum();
+            if actual != size { return Err(JsonError::SizeMismatch { name, declared: size, actual }); }
+            Node::Directory(size, contents)
+        },
+        Some(_) => return Err(JsonError::UnexpectedType("children")),
+    };
+
+    Ok((name, node))
+}
+
+fn human_size(size: usize) -> String {
+    if size < 1024 { return size.to_string(); }
+
+    let mut value = size as f64;
+    let mut unit = "";
+    for next_unit in ["K", "M", "G", "T", "P"] {
+        value /= 1024.0;
+        unit = next_unit;
+        if value < 1024.0 { break; }
+    }
+
+    if value < 10.0 { format!("{:.1}{}", value, unit) } else { format!("{:.0}{}", value, unit) }
 }
 
 impl<'a> NodeIterator<'a> {
 
     fn new(node: &'a Node) -> Self {
-        Self { node_stack: vec![node] }
+        Self { node_stack: vec![("/".to_string(), node)] }
     }
 }
 
 impl<'a> Iterator for NodeIterator<'a> {
-    type Item = &'a Node;
+    type Item = (String, &'a Node);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(node) = self.node_stack.pop() {
+        if let Some((path, node)) = self.node_stack.pop() {
             if let Node::Directory(_, contents) = node {
-                self.node_stack.extend(contents.values());
+                self.node_stack.extend(contents.iter().map(|(name, child)| (join_path(&path, name), child)));
             }
-            Some(node)
+            Some((path, node))
         } else {
             None
         }
     }
 }
 
+#[derive(Clone)]
+enum TranscriptLine {
+    Cd(String),
+    Ls,
+    Dir(String),
+    File(usize, String),
+}
+
+enum Json {
+    Number(usize),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+#[derive(Debug)]
+enum JsonError {
+    Syntax,
+    MissingField(&'static str),
+    UnexpectedType(&'static str),
+    DuplicateName(String),
+    SizeMismatch { name: String, declared: usize, actual: usize },
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JsonError::Syntax => write!(f, "malformed JSON document"),
+            JsonError::MissingField(field) => write!(f, "node is missing the `{}` field", field),
+            JsonError::UnexpectedType(field) => write!(f, "`{}` has the wrong type", field),
+            JsonError::DuplicateName(name) => write!(f, "`{}` appears more than once in the same directory", name),
+            JsonError::SizeMismatch { name, declared, actual } =>
+                write!(f, "directory `{}` declares size {} but its children add up to {}", name, declared, actual),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum TranscriptError {
+    Unparseable { line: usize },
+    OutputWithoutLs { line: usize },
+    NoSuchDirectory { line: usize, path: String },
+    ConflictingListing { line: usize, name: String },
+    IncompleteListing { line: usize, name: String },
+}
+
+impl fmt::Display for TranscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TranscriptError::Unparseable { line } => write!(f, "line {}: unrecognised transcript line", line),
+            TranscriptError::OutputWithoutLs { line } => write!(f, "line {}: directory listing without a preceding `ls`", line),
+            TranscriptError::NoSuchDirectory { line, path } => write!(f, "line {}: no such directory `{}`", line, path),
+            TranscriptError::ConflictingListing { line, name } => write!(f, "line {}: `{}` conflicts with an earlier listing", line, name),
+            TranscriptError::IncompleteListing { line, name } => write!(f, "line {}: listing is missing `{}`, which is known to exist", line, name),
+        }
+    }
+}
+
 // BEGIN NOM PARSING CODE
 
 fn identifier(input: &str) -> IResult<&str, &str> {
@@ -71,51 +318,174 @@ fn number(input: &str) -> IResult<&str, usize> {
     map_res(recognize(digit1), str::parse)(input)
 }
 
-fn on_line<'a, O, E: ParseError<&'a str>, F: Parser<&'a str, O, E>>(f: F) -> impl FnMut(&'a str) -> IResult<&'a str, O, E> {
-    terminated(f, opt(line_ending))
+fn transcript_line(input: &str) -> IResult<&str, TranscriptLine> {
+    alt((
+        map(preceded(tuple((tag("$ cd"), space1)), identifier), |path| TranscriptLine::Cd(path.to_string())),
+        value(TranscriptLine::Ls, tag("$ ls")),
+        map(preceded(tuple((tag("dir"), space1)), identifier), |name| TranscriptLine::Dir(name.to_string())),
+        map(tuple((number, space1, identifier)), |(size, _, name)| TranscriptLine::File(size, name.to_string())),
+    ))(input)
 }
 
-fn dummy_directory(input: &str) -> IResult<&str, Option<(&str, Node)>> {
-    on_line(value(None, tuple((tag("dir"), space1, identifier))))(input)
+fn parse_line(line: &str) -> Option<TranscriptLine> {
+    all_consuming(transcript_line)(line).ok().map(|(_, parsed)| parsed)
 }
 
-fn file(input: &str) -> IResult<&str, Option<(&str, Node)>> {
-    on_line(map(tuple((number, space1, identifier)), |(size, _, name)| Some((name, Node::File(size)))))(input)
+fn ws<'a, O, E: ParseError<&'a str>, F: Parser<&'a str, O, E>>(f: F) -> impl FnMut(&'a str) -> IResult<&'a str, O, E> {
+    delimited(multispace0, f, multispace0)
 }
 
-fn cd_down(input: &str) -> IResult<&str, &str> {
-    on_line(preceded(tuple((tag("$ cd"), space1)), identifier))(input)
+fn utf16_code_unit(input: &str) -> IResult<&str, u16> {
+    map_res(take_while_m_n(4, 4, |c: char| c.is_ascii_hexdigit()), |hex| u16::from_str_radix(hex, 16))(input)
 }
 
-fn ls(input: &str) -> IResult<&str, ()> {
-    on_line(value((), tag("$ ls")))(input)
+fn unicode_escape(input: &str) -> IResult<&str, char> {
+    alt((
+        map_opt(separated_pair(utf16_code_unit, tag("\\u"), utf16_code_unit), |(high, low)| {
+            if !(0xd800..0xdc00).contains(&high) || !(0xdc00..0xe000).contains(&low) { return None; }
+            char::decode_utf16([high, low]).next()?.ok()
+        }),
+        map_opt(utf16_code_unit, |unit| char::from_u32(unit as u32)),
+    ))(input)
 }
 
-fn cd_up(input: &str) -> IResult<&str, ()> {
-    on_line(value((), tuple((tag("$ cd"), space1, tag("..")))))(input)
+fn json_string(input: &str) -> IResult<&str, String> {
+    let escape = alt((
+        value('\\', char('\\')),
+        value('"', char('"')),
+        value('/', char('/')),
+        value('\u{8}', char('b')),
+        value('\u{c}', char('f')),
+        value('\n', char('n')),
+        value('\r', char('r')),
+        value('\t', char('t')),
+        preceded(char('u'), unicode_escape),
+    ));
+    delimited(char('"'), map(opt(escaped_transform(none_of("\\\""), '\\', escape)), Option::unwrap_or_default), char('"'))(input)
 }
 
-fn directory(input: &str) -> IResult<&str, Option<(&str, Node)>> {
-    map(
-        terminated(
-            tuple((
-                terminated(cd_down, ls),
-                map(
-                    many0(alt((dummy_directory, file, directory))),
-                    |items| HashMap::from_iter(items.into_iter().filter_map(|item| item.map(|(name, node)| (name.to_string(), node))))
-                )
-            )),
-            opt(cd_up)
-        ), 
-        |(name, contents)| Some((name, Node::Directory(contents.values().map(|n| n.size()).sum(), contents)))
-    )(input)
+fn json_value(input: &str) -> IResult<&str, Json> {
+    ws(alt((
+        map(json_string, Json::String),
+        map(number, Json::Number),
+        map(delimited(char('['), separated_list0(char(','), json_value), ws(char(']'))), Json::Array),
+        map(delimited(char('{'), separated_list0(char(','), separated_pair(ws(json_string), char(':'), json_value)), ws(char('}'))), Json::Object),
+    )))(input)
 }
 
-fn parse(input: &str) -> Node {
-    directory(input).expect("failed to parse input").1.unwrap().1
+// END NOM PARSING CODE
+
+struct Listing {
+    line: usize,
+    seen: HashSet<String>,
 }
 
-// END NOM PARSING CODE
+struct Shell {
+    root: Node,
+    cwd: Vec<String>,
+    listing: Option<Listing>,
+    listed: HashSet<Vec<String>>,
+}
+
+impl Shell {
+
+    fn new() -> Self {
+        Self { root: Node::Directory(0, HashMap::new()), cwd: Vec::new(), listing: None, listed: HashSet::new() }
+    }
+
+    fn change_directory(&mut self, path: &str, line: usize) -> Result<(), TranscriptError> {
+        let mut cwd = if path.starts_with('/') { Vec::new() } else { self.cwd.clone() };
+        for component in path.split('/') {
+            match component {
+                "" | "." => {},
+                ".."     => { cwd.pop(); },
+                name     => {
+                    let listed = self.listed.contains(&cwd);
+                    if let Some(Node::Directory(_, contents)) = self.root.find_mut(&cwd) {
+                        if !listed { contents.entry(name.to_string()).or_insert_with(|| Node::Directory(0, HashMap::new())); }
+                    }
+                    cwd.push(name.to_string());
+                    if !matches!(self.root.find(&cwd), Some(Node::Directory(_, _))) {
+                        return Err(TranscriptError::NoSuchDirectory { line, path: path.to_string() });
+                    }
+                },
+            }
+        }
+        self.cwd = cwd;
+        Ok(())
+    }
+
+    fn record_entry(&mut self, name: &str, entry: Node, line: usize) -> Result<(), TranscriptError> {
+        let relisting = self.listed.contains(&self.cwd);
+        if let Some(listing) = self.listing.as_mut() { listing.seen.insert(name.to_string()); }
+        if let Some(Node::Directory(_, contents)) = self.root.find_mut(&self.cwd) {
+            match (contents.get(name), &entry) {
+                (None, _) if !relisting => { contents.insert(name.to_string(), entry); },
+                (Some(Node::File(old)), Node::File(new)) if old == new => {},
+                (Some(Node::Directory(_, _)), Node::Directory(_, _)) => {},
+                _ => return Err(TranscriptError::ConflictingListing { line, name: name.to_string() }),
+            }
+        }
+        Ok(())
+    }
+
+    fn finish_listing(&mut self) -> Result<(), TranscriptError> {
+        if let Some(listing) = self.listing.take() {
+            if let Some(Node::Directory(_, contents)) = self.root.find(&self.cwd) {
+                if let Some(name) = contents.keys().filter(|name| !listing.seen.contains(*name)).min() {
+                    return Err(TranscriptError::IncompleteListing { line: listing.line, name: name.clone() });
+                }
+            }
+            self.listed.insert(self.cwd.clone());
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, command: TranscriptLine, line: usize) -> Result<(), TranscriptError> {
+        match command {
+            TranscriptLine::Cd(path) => {
+                self.finish_listing()?;
+                self.change_directory(&path, line)
+            },
+            TranscriptLine::Ls => {
+                self.finish_listing()?;
+                self.listing = Some(Listing { line, seen: HashSet::new() });
+                Ok(())
+            },
+            TranscriptLine::Dir(_) | TranscriptLine::File(_, _) if self.listing.is_none() => Err(TranscriptError::OutputWithoutLs { line }),
+            TranscriptLine::Dir(name) => self.record_entry(&name, Node::Directory(0, HashMap::new()), line),
+            TranscriptLine::File(size, name) => self.record_entry(&name, Node::File(size), line),
+        }
+    }
+
+    fn into_root(mut self) -> Result<Node, TranscriptError> {
+        self.finish_listing()?;
+        self.root.update_sizes();
+        Ok(self.root)
+    }
+}
+
+fn interpret(transcript: &str) -> Result<Node, TranscriptError> {
+    let mut shell = Shell::new();
+    for (idx, line) in transcript.lines().enumerate().filter(|(_, line)| !line.is_empty()) {
+        let command = parse_line(line).ok_or(TranscriptError::Unparseable { line: idx + 1 })?;
+        shell.execute(command, idx + 1)?;
+    }
+    shell.into_root()
+}
+
+fn report_options<I: Iterator<Item = String>>(args: I) -> (String, SortOrder, Option<usize>) {
+    let (mut path, mut order, mut max_depth) = ("/".to_string(), SortOrder::Name, None);
+    for arg in args {
+        match arg.as_str() {
+            "name"                    => order = SortOrder::Name,
+            "size"                    => order = SortOrder::Size,
+            _ if arg.starts_with('/') => path = arg,
+            _                         => max_depth = Some(arg.parse().unwrap_or_else(|_| panic!("unrecognised option `{}`", arg))),
+        }
+    }
+    (path, order, max_depth)
+}
 
 fn read_data() -> String {
     fs::read_to_string("inputs/07.txt").expect("unable to read input file")
@@ -123,13 +493,73 @@ fn read_data() -> String {
 
 fn main() {
     let terminal_output = read_data();
-    let root_node = parse(&terminal_output);
-    
-    let small_directories = root_node.iter().filter(|n| n.is_directory() && n.size() <= 100_000);
-    println!("Part 1: {}", small_directories.map(|n| n.size()).sum::<usize>());
-
-    let space_required = root_node.size() - 40_000_000;
-    let large_directories = root_node.iter().filter(|n| n.is_directory() && n.size() >= space_required);
-    let directory_to_delete = large_directories.min_by_key(|n| n.size()).expect("no directories large enough");
-    println!("Part 2: {}", directory_to_delete.size());
+    let root_node = if terminal_output.trim_start().starts_with('{') {
+        Node::from_json(&terminal_output).unwrap_or_else(|err| panic!("{}", err))
+    } else {
+        interpret(&terminal_output).unwrap_or_else(|err| panic!("{}", err))
+    };
+
+    match env::args().nth(1).as_deref() {
+        Some("json") => { println!("{}", root_node.to_json()); return; },
+        Some(mode @ ("tree" | "du")) => {
+            let (path, order, max_depth) = report_options(env::args().skip(2));
+            let node = root_node.lookup(&path).unwrap_or_else(|| panic!("no such path `{}`", path));
+            println!("{}", if mode == "tree" { node.render_tree(&path, order, max_depth) } else { node.render_du(&path, order, max_depth) });
+            return;
+        },
+        _ => {},
+    }
+
+    let small_directories = root_node.iter().filter(|(_, n)| n.is_directory() && n.size() <= 100_000);
+    println!("Part 1: {}", small_directories.map(|(_, n)| n.size()).sum::<usize>());
+
+    let (directory_to_delete, size_to_delete) = root_node.smallest_directory_freeing(70_000_000, 30_000_000).expect("no directories large enough");
+    println!("Part 2: {}", size_to_delete);
+    println!("Directory to delete: {}", directory_to_delete);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_json_decodes_all_string_escapes() {
+        let document = r#"{"name": "/", "size": 3, "children": [{"name": "caf\u00e9 \ud83d\ude00\b\f\/", "size": 3}]}"#;
+        let expected = Node::Directory(3, HashMap::from([("caf\u{e9} \u{1f600}\u{8}\u{c}/".to_string(), Node::File(3))]));
+        assert_eq!(Node::from_json(document).unwrap(), expected);
+        assert!(Node::from_json(r#"{"name": "\ud83d", "size": 0}"#).is_err());
+    }
+
+    #[test]
+    fn from_json_rejects_duplicate_names_and_wrong_sizes() {
+        let duplicate = r#"{"name":"/","size":5,"children":[{"name":"a","size":1},{"name":"a","size":4}]}"#;
+        assert!(matches!(Node::from_json(duplicate), Err(JsonError::DuplicateName(name)) if name == "a"));
+        let wrong_size = r#"{"name":"/","size":6,"children":[{"name":"a","size":1},{"name":"b","size":4}]}"#;
+        assert!(matches!(Node::from_json(wrong_size), Err(JsonError::SizeMismatch { declared: 6, actual: 5, .. })));
+    }
+
+    #[test]
+    fn cd_into_unlisted_directories_and_conflicting_relistings() {
+        let root = interpret("$ cd /\n$ cd a\n$ ls\n10 f\n$ cd /\n$ ls\ndir a\n5 g").unwrap();
+        assert_eq!(root.size(), 15);
+
+        assert!(matches!(interpret("$ ls\ndir a\n$ cd b"), Err(TranscriptError::NoSuchDirectory { line: 3, .. })));
+        assert!(matches!(interpret("$ ls\n5 a\n$ cd a"), Err(TranscriptError::NoSuchDirectory { line: 3, .. })));
+        assert!(matches!(interpret("$ cd a\n$ cd /\n$ ls\n5 g"), Err(TranscriptError::IncompleteListing { line: 3, .. })));
+        assert!(matches!(interpret("$ ls\n10 a\n$ ls\n20 b"), Err(TranscriptError::ConflictingListing { line: 4, .. })));
+        assert!(matches!(interpret("$ ls\n10 a\n20 b\n$ ls\n20 b"), Err(TranscriptError::IncompleteListing { line: 4, .. })));
+        assert!(interpret("$ ls\n10 a\ndir b\n$ cd b\n$ cd ..\n$ ls\ndir b\n10 a").is_ok());
+    }
+
+    #[test]
+    fn json_round_trip_keeps_tree_and_sizes() {
+        let transcript = "$ cd /\n$ ls\ndir a\n14848514 b.txt\n8504156 c.dat\ndir d\n$ cd a\n$ ls\ndir e\n29116 f\n2557 g\n62596 h.lst\n\
+                          $ cd e\n$ ls\n584 i\n$ cd ..\n$ cd ..\n$ cd d\n$ ls\n4060174 j\n8033020 d.log\n5626152 \"caf\u{e9}\"\\\t.ext\n7214296 k";
+        let root = interpret(transcript).unwrap();
+        assert_eq!(root.size(), 48381165);
+
+        let restored = Node::from_json(&root.to_json()).unwrap();
+        assert_eq!(restored, root);
+        assert_eq!(restored.lookup("/a/e").map(Node::size), Some(584));
+    }
 }
@@ -1,9 +1,31 @@
-use itertools::iproduct;
-use take_until::TakeUntilExt;
-
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
+#[derive(Clone, Copy, Default)]
+struct Sightline {
+    distance: usize,
+    reaches_edge: bool,
+}
+
+#[derive(Clone, Copy, Default)]
+struct TreeView {
+    left: Sightline,
+    right: Sightline,
+    up: Sightline,
+    down: Sightline,
+}
+
+impl TreeView {
+
+    fn is_visible(&self) -> bool {
+        [self.left, self.right, self.up, self.down].iter().any(|sightline| sightline.reaches_edge)
+    }
+
+    fn scenic_score(&self) -> usize {
+        [self.left, self.right, self.up, self.down].iter().map(|sightline| sightline.distance).product()
+    }
+}
+
 fn read_lines() -> impl Iterator<Item = String> {
     let file = File::open("inputs/08.txt").expect("input file not present");
     BufReader::new(file).lines().map(|l| l.expect("error reading from file"))
@@ -13,35 +35,56 @@ fn parse_input<I: Iterator<Item = String>>(lines: I) -> Vec<Vec<usize>> {
     lines.map(|line| line.chars().map(|c| c.to_digit(10).expect("not a digit") as usize).collect()).collect()
 }
 
-fn is_visible(row: usize, col: usize, trees: &[Vec<usize>]) -> bool {
-    let visible_from_left = (0..col).map(|c| trees[row][c]).all(|t| t < trees[row][col]);
-    let visible_from_right = (col + 1..trees[row].len()).map(|c| trees[row][c]).all(|t| t < trees[row][col]);
-    let visible_from_above = (0..row).map(|r| trees[r][col]).all(|t| t < trees[row][col]);
-    let visible_from_below = (row + 1..trees.len()).map(|r| trees[r][col]).all(|t| t < trees[row][col]);
-
-    visible_from_left || visible_from_right || visible_from_above || visible_from_below
+fn sightlines<I: Iterator<Item = usize>>(heights: I) -> Vec<Sightline> {
+    let mut blockers: Vec<(usize, usize)> = Vec::new();
+    heights.enumerate().map(|(idx, height)| {
+        while blockers.last().is_some_and(|&(_, h)| h < height) { blockers.pop(); }
+        let sightline = match blockers.last() {
+            Some(&(blocker, _)) => Sightline { distance: idx - blocker, reaches_edge: false },
+            None                => Sightline { distance: idx, reaches_edge: true },
+        };
+        blockers.push((idx, height));
+        sightline
+    }).collect()
 }
 
-fn visible_count(trees: &[Vec<usize>]) -> usize {
-    iproduct!(0..trees.len(), 0..trees[0].len()).filter(|&(r, c)| is_visible(r, c, trees)).count()
-}
+fn survey(trees: &[Vec<usize>]) -> Vec<Vec<TreeView>> {
+    let (rows, cols) = (trees.len(), trees.first().map_or(0, |row| row.len()));
+    let mut views = vec![vec![TreeView::default(); cols]; rows];
 
-fn scenic_score(row: usize, col: usize, trees: &[Vec<usize>]) -> usize {
-    let score_left = (0..col).rev().map(|c| trees[row][c]).take_until(|&t| t >= trees[row][col]).count();
-    let score_right = (col + 1..trees[row].len()).map(|c| trees[row][c]).take_until(|&t| t >= trees[row][col]).count();
-    let score_above = (0..row).rev().map(|r| trees[r][col]).take_until(|&t| t >= trees[row][col]).count();
-    let score_below = (row + 1..trees.len()).map(|r| trees[r][col]).take_until(|&t| t >= trees[row][col]).count();
+    for row in 0..rows {
+        let from_left = sightlines((0..cols).map(|c| trees[row][c]));
+        let from_right = sightlines((0..cols).rev().map(|c| trees[row][c]));
+        for col in 0..cols {
+            views[row][col].left = from_left[col];
+            views[row][col].right = from_right[cols - 1 - col];
+        }
+    }
+
+    for col in 0..cols {
+        let from_above = sightlines((0..rows).map(|r| trees[r][col]));
+        let from_below = sightlines((0..rows).rev().map(|r| trees[r][col]));
+        for row in 0..rows {
+            views[row][col].up = from_above[row];
+            views[row][col].down = from_below[rows - 1 - row];
+        }
+    }
+
+    views
+}
 
-    score_left * score_right * score_above * score_below
+fn visible_count(views: &[Vec<TreeView>]) -> usize {
+    views.iter().flatten().filter(|view| view.is_visible()).count()
 }
 
-fn maximum_scenic_score(trees: &[Vec<usize>]) -> usize {
-    iproduct!(0..trees.len(), 0..trees[0].len()).map(|(r, c)| scenic_score(r, c, trees)).max().unwrap()
+fn maximum_scenic_score(views: &[Vec<TreeView>]) -> usize {
+    views.iter().flatten().map(|view| view.scenic_score()).max().unwrap_or(0)
 }
 
 fn main() {
     let trees = parse_input(read_lines());
+    let views = survey(&trees);
 
-    println!("Part 1: {}", visible_count(&trees));
-    println!("Part 2: {}", maximum_scenic_score(&trees));
+    println!("Part 1: {}", visible_count(&views));
+    println!("Part 2: {}", maximum_scenic_score(&views));
 }
@@ -1,60 +1,240 @@
 use itertools::Itertools;
 
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::iter::repeat;
 
 type Coordinate = (isize, isize);
-type Movement = (Coordinate, usize);
+
+#[derive(Clone, Copy)]
+enum Movement {
+    Relative(Coordinate, usize),
+    MoveTo(Coordinate),
+}
+
+#[derive(Debug)]
+enum MovementError {
+    UnknownDirection(String),
+    InvalidAmount(String),
+    InvalidTarget(String),
+    Malformed(String),
+}
+
+impl fmt::Display for MovementError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovementError::UnknownDirection(direction) => write!(f, "unknown direction `{}`", direction),
+            MovementError::InvalidAmount(amount) => write!(f, "could not parse `{}` as an amount", amount),
+            MovementError::InvalidTarget(target) => write!(f, "could not parse `{}` as a target position", target),
+            MovementError::Malformed(movement) => write!(f, "malformed movement `{}`", movement),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Following {
+    Orthogonal,
+    Diagonal,
+}
+
+#[derive(Clone, Copy)]
+struct RopeRules {
+    slack: usize,
+    following: Following,
+}
+
+impl Default for RopeRules {
+    fn default() -> Self {
+        Self { slack: 1, following: Following::Diagonal }
+    }
+}
+
+impl RopeRules {
+
+    fn is_slack(&self, (dr, dc): Coordinate) -> bool {
+        match self.following {
+            Following::Orthogonal => dr.unsigned_abs() + dc.unsigned_abs() <= self.slack,
+            Following::Diagonal   => dr.unsigned_abs().max(dc.unsigned_abs()) <= self.slack,
+        }
+    }
+
+    fn step_towards(&self, (dr, dc): Coordinate) -> Coordinate {
+        match self.following {
+            Following::Orthogonal if dr.abs() >= dc.abs() => (dr.signum(), 0),
+            Following::Orthogonal                         => (0, dc.signum()),
+            Following::Diagonal                           => (dr.signum(), dc.signum()),
+        }
+    }
+}
+
+struct Rope {
+    rules: RopeRules,
+    knots: Vec<Coordinate>,
+    visited: Vec<HashSet<Coordinate>>,
+}
+
+impl Rope {
+
+    fn new(length: usize, rules: RopeRules) -> Option<Self> {
+        (length > 0).then(|| Self { rules, knots: vec![(0, 0); length], visited: vec![HashSet::from([(0, 0)]); length] })
+    }
+
+    fn tail(&self) -> usize {
+        self.knots.len() - 1
+    }
+
+    fn drag_position(&mut self, idx: usize) {
+        loop {
+            let (leader, follower) = (self.knots[idx - 1], self.knots[idx]);
+            let delta = (leader.0 - follower.0, leader.1 - follower.1);
+            if self.rules.is_slack(delta) { break; }
+
+            let step = self.rules.step_towards(delta);
+            self.knots[idx] = (follower.0 + step.0, follower.1 + step.1);
+            self.visited[idx].insert(self.knots[idx]);
+        }
+    }
+
+    fn apply_step(&mut self, step: Coordinate) {
+        self.knots[0] = (self.knots[0].0 + step.0, self.knots[0].1 + step.1);
+        self.visited[0].insert(self.knots[0]);
+        for idx in 1..self.knots.len() {
+            self.drag_position(idx);
+        }
+    }
+
+    fn apply_movement(&mut self, movement: Movement) {
+        match movement {
+            Movement::Relative(step, amount) => {
+                for _ in 0..amount { self.apply_step(step); }
+            },
+            Movement::MoveTo(target) => {
+                while self.knots[0] != target {
+                    let head = self.knots[0];
+                    self.apply_step(((target.0 - head.0).signum(), (target.1 - head.1).signum()));
+                }
+            },
+        }
+    }
+
+    fn visited(&self, knot: usize) -> &HashSet<Coordinate> {
+        &self.visited[knot]
+    }
+
+    fn render<F: Fn(Coordinate) -> char>(&self, cell: F) -> String {
+        let cells = self.visited.iter().flatten().chain(self.knots.iter());
+        let (min_row, max_row) = cells.clone().map(|&(r, _)| r).minmax().into_option().unwrap();
+        let (min_col, max_col) = cells.map(|&(_, c)| c).minmax().into_option().unwrap();
+        (min_row..=max_row).map(|r| (min_col..=max_col).map(|c| cell((r, c))).collect::<String>()).join("\n")
+    }
+
+    fn render_visited(&self, knot: usize) -> String {
+        self.render(|coord| match coord {
+            (0, 0)                                      => 's',
+            coord if self.visited[knot].contains(&coord) => '#',
+            _                                           => '.',
+        })
+    }
+
+    fn render_knots(&self) -> String {
+        self.render(|coord| match self.knots.iter().position(|&knot| knot == coord) {
+            Some(0)   => 'H',
+            Some(idx) => char::from_digit(idx as u32, 36).unwrap_or('*'),
+            None      => if coord == (0, 0) { 's' } else { '.' },
+        })
+    }
+}
 
 fn read_lines() -> impl Iterator<Item = String> {
     let file = File::open("inputs/09.txt").expect("input file not present");
     BufReader::new(file).lines().map(|l| l.expect("error reading from file"))
 }
 
-fn parse_movement(movement: &str) -> Movement {
-    let (direction, amount) = movement.split(" ").collect_tuple().expect("too many items on line");
-    let parsed_amount = amount.parse().expect("could not parse amount as integer");
+fn parse_direction(direction: &str) -> Result<Coordinate, MovementError> {
     match direction {
-        "R" => ((0, 1), parsed_amount),
-        "L" => ((0, -1), parsed_amount),
-        "D" => ((1, 0), parsed_amount),
-        "U" => ((-1, 0), parsed_amount),
-        _   => panic!("unexpected direction")
+        "R"         => Ok((0, 1)),
+        "L"         => Ok((0, -1)),
+        "D"         => Ok((1, 0)),
+        "U"         => Ok((-1, 0)),
+        "UR" | "RU" => Ok((-1, 1)),
+        "UL" | "LU" => Ok((-1, -1)),
+        "DR" | "RD" => Ok((1, 1)),
+        "DL" | "LD" => Ok((1, -1)),
+        _           => Err(MovementError::UnknownDirection(direction.to_string())),
     }
 }
 
-fn drag_position(leader: Coordinate, follower: Coordinate) -> Coordinate {
-    let delta = (leader.0 - follower.0, leader.1 - follower.1);
-    match delta {
-        (-1..=1, -1..=1) => follower,
-        (x, y)           => (follower.0 + x.signum(), follower.1 + y.signum()),
-    }
+fn parse_target(target: &str) -> Result<Coordinate, MovementError> {
+    let invalid = || MovementError::InvalidTarget(target.to_string());
+    let (x, y) = target.split(',').map(|v| v.trim().parse::<isize>()).collect_tuple().ok_or_else(invalid)?;
+    Ok((-y.map_err(|_| invalid())?, x.map_err(|_| invalid())?))
 }
 
-fn apply_movement(knots: &mut [Coordinate], step: Coordinate) {
-    knots[0] = (knots[0].0 + step.0, knots[0].1 + step.1);
-    for idx in 1..knots.len() {
-        knots[idx] = drag_position(knots[idx - 1], knots[idx]);
+fn parse_movement(movement: &str) -> Result<Movement, MovementError> {
+    if let Some(target) = movement.strip_prefix("move to ") {
+        return parse_target(target).map(Movement::MoveTo);
     }
+
+    let (direction, amount) = movement.split(' ').collect_tuple().ok_or_else(|| MovementError::Malformed(movement.to_string()))?;
+    let parsed_amount = amount.parse().map_err(|_| MovementError::InvalidAmount(amount.to_string()))?;
+    Ok(Movement::Relative(parse_direction(direction)?, parsed_amount))
 }
 
-fn tail_positions<'a>(knots: &'a mut [Coordinate], movements: &'a [Movement]) -> impl Iterator<Item = Coordinate> + 'a {
-    let steps = movements.iter().flat_map(|&(step, amount)| repeat(step).take(amount));
-    steps.scan(knots, |positions, step| {
-        apply_movement(positions, step);
-        positions.last().copied()
-    })
+fn render_options<I: Iterator<Item = String>>(args: I) -> (usize, RopeRules) {
+    let (mut length, mut rules) = (10, RopeRules::default());
+    for arg in args {
+        match (arg.as_str(), arg.strip_prefix("slack=")) {
+            ("orthogonal", _) => rules.following = Following::Orthogonal,
+            ("diagonal", _)   => rules.following = Following::Diagonal,
+            (_, Some(slack))  => rules.slack = slack.parse().unwrap_or_else(|_| panic!("unrecognised slack `{}`", slack)),
+            (_, None)         => length = arg.parse().unwrap_or_else(|_| panic!("unrecognised option `{}`", arg)),
+        }
+    }
+    (length, rules)
 }
 
 fn main() {
-    let movements: Vec<_> = read_lines().map(|s| parse_movement(&s)).collect();
+    let movements: Vec<_> = read_lines().enumerate()
+        .map(|(idx, s)| parse_movement(&s).unwrap_or_else(|err| panic!("line {}: {}", idx + 1, err)))
+        .collect();
 
-    let mut knot_positions_part_1 = vec![(0, 0); 2];
-    let tail_positions_part_1 = tail_positions(&mut knot_positions_part_1, &movements);
-    println!("Part 1: {}", tail_positions_part_1.unique().count());
+    if env::args().nth(1).as_deref() == Some("render") {
+        let (length, rules) = render_options(env::args().skip(2));
+        let mut rope = Rope::new(length, rules).expect("a rope needs at least one knot");
+        for &movement in &movements { rope.apply_movement(movement); }
+        println!("{}\n\n{}", rope.render_visited(rope.tail()), rope.render_knots());
+        return;
+    }
+
+    let mut rope_part_1 = Rope::new(2, RopeRules::default()).expect("a rope needs at least one knot");
+    for &movement in &movements { rope_part_1.apply_movement(movement); }
+    println!("Part 1: {}", rope_part_1.visited(rope_part_1.tail()).len());
 
-    let mut knot_positions_part_2 = vec![(0, 0); 10];
-    let tail_positions_part_2 = tail_positions(&mut knot_positions_part_2, &movements);
-    println!("Part 2: {}", tail_positions_part_2.unique().count());
+    let mut rope_part_2 = Rope::new(10, RopeRules::default()).expect("a rope needs at least one knot");
+    for &movement in &movements { rope_part_2.apply_movement(movement); }
+    println!("Part 2: {}", rope_part_2.visited(rope_part_2.tail()).len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orthogonal_following_with_slack_takes_one_axis_at_a_time() {
+        let mut rope = Rope::new(2, RopeRules { slack: 2, following: Following::Orthogonal }).unwrap();
+        for movement in ["R 4", "U 2"] { rope.apply_movement(parse_movement(movement).unwrap()); }
+
+        assert_eq!(rope.visited(1), &HashSet::from([(0, 0), (0, 1), (0, 2), (0, 3), (-1, 3)]));
+        assert_eq!(rope.render_visited(1), ".....\n...#.\ns###.");
+        assert_eq!(rope.render_knots(), "....H\n...1.\ns....");
+    }
+
+    #[test]
+    fn render_options_override_the_defaults() {
+        let (length, rules) = render_options(["orthogonal", "slack=3", "4"].into_iter().map(str::to_string));
+        assert_eq!((length, rules.slack), (4, 3));
+        assert!(matches!(rules.following, Following::Orthogonal));
+    }
 }
@@ -1,66 +1,430 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::env;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::iter::repeat;
+use std::io::{self, BufRead, BufReader, Write};
 
-enum Instruction {
-    Addx(isize),
-    Noop
+#[derive(Clone, Copy)]
+struct Registers {
+    x: isize,
+    pc: usize,
 }
 
-impl Instruction {
+impl Default for Registers {
+    fn default() -> Self {
+        Self { x: 1, pc: 0 }
+    }
+}
+
+trait Instruction: fmt::Display {
+    fn cycles(&self) -> usize;
+    fn execute(&self, registers: &mut Registers);
+}
+
+struct Addx(isize);
+
+impl Instruction for Addx {
+    fn cycles(&self) -> usize { 2 }
+    fn execute(&self, registers: &mut Registers) { registers.x += self.0; }
+}
+
+impl fmt::Display for Addx {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "addx {}", self.0)
+    }
+}
+
+struct Noop;
+
+impl Instruction for Noop {
+    fn cycles(&self) -> usize { 1 }
+    fn execute(&self, _registers: &mut Registers) {}
+}
+
+impl fmt::Display for Noop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "noop")
+    }
+}
+
+type Program = Vec<Box<dyn Instruction>>;
+type Decoder = fn(&[&str]) -> Option<Box<dyn Instruction>>;
+
+fn decode_addx(operands: &[&str]) -> Option<Box<dyn Instruction>> {
+    match operands {
+        [amount] => amount.parse().ok().map(|amount| Box::new(Addx(amount)) as Box<dyn Instruction>),
+        _        => None,
+    }
+}
+
+fn decode_noop(operands: &[&str]) -> Option<Box<dyn Instruction>> {
+    operands.is_empty().then(|| Box::new(Noop) as Box<dyn Instruction>)
+}
+
+fn instruction_set() -> HashMap<&'static str, Decoder> {
+    HashMap::from([
+        ("addx", decode_addx as Decoder),
+        ("noop", decode_noop as Decoder),
+    ])
+}
+
+#[derive(Debug)]
+enum ProgramError {
+    UnknownOpcode { line: usize, opcode: String },
+    BadOperands { line: usize, text: String },
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProgramError::UnknownOpcode { line, opcode } => write!(f, "line {}: unknown opcode `{}`", line, opcode),
+            ProgramError::BadOperands { line, text } => write!(f, "line {}: bad operands in `{}`", line, text),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct TraceEntry<'a> {
+    cycle: usize,
+    x: isize,
+    instruction: &'a dyn Instruction,
+}
+
+impl fmt::Display for TraceEntry<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cycle {:>4}  X={:>4}  {}", self.cycle, self.x, self.instruction)
+    }
+}
+
+struct Cpu<'a> {
+    program: &'a [Box<dyn Instruction>],
+    registers: Registers,
+    cycle: usize,
+    elapsed: usize,
+}
+
+impl<'a> Cpu<'a> {
+
+    fn new(program: &'a [Box<dyn Instruction>]) -> Self {
+        Self { program, registers: Registers::default(), cycle: 0, elapsed: 0 }
+    }
+}
+
+impl<'a> Iterator for Cpu<'a> {
+    type Item = TraceEntry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let instruction = self.program.get(self.registers.pc)?;
+        self.cycle += 1;
+        self.elapsed += 1;
+
+        let entry = TraceEntry { cycle: self.cycle, x: self.registers.x, instruction: instruction.as_ref() };
+        if self.elapsed >= instruction.cycles() {
+            self.elapsed = 0;
+            self.registers.pc += 1;
+            instruction.execute(&mut self.registers);
+        }
+        Some(entry)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "<"  => Some(Comparison::Less),
+            "<=" => Some(Comparison::LessOrEqual),
+            "==" => Some(Comparison::Equal),
+            "!=" => Some(Comparison::NotEqual),
+            ">=" => Some(Comparison::GreaterOrEqual),
+            ">"  => Some(Comparison::Greater),
+            _    => None,
+        }
+    }
+
+    fn holds(&self, lhs: isize, rhs: isize) -> bool {
+        match self {
+            Comparison::Less           => lhs < rhs,
+            Comparison::LessOrEqual    => lhs <= rhs,
+            Comparison::Equal          => lhs == rhs,
+            Comparison::NotEqual       => lhs != rhs,
+            Comparison::GreaterOrEqual => lhs >= rhs,
+            Comparison::Greater        => lhs > rhs,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Comparison::Less           => "<",
+            Comparison::LessOrEqual    => "<=",
+            Comparison::Equal          => "==",
+            Comparison::NotEqual       => "!=",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Greater        => ">",
+        };
+        write!(f, "{}", symbol)
+    }
+}
 
-    fn cycles(&self) -> usize {
+#[derive(Clone, Copy)]
+enum Breakpoint {
+    Cycle(usize),
+    Watch(Comparison, isize),
+}
+
+impl Breakpoint {
+
+    fn triggered(&self, entry: &TraceEntry, previous_x: Option<isize>) -> bool {
+        match *self {
+            Breakpoint::Cycle(cycle)            => entry.cycle == cycle,
+            Breakpoint::Watch(comparison, rhs) => {
+                comparison.holds(entry.x, rhs) && !previous_x.is_some_and(|x| comparison.holds(x, rhs))
+            },
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Instruction::Addx(_) => 2,
-            Instruction::Noop    => 1,
+            Breakpoint::Cycle(cycle)            => write!(f, "cycle {}", cycle),
+            Breakpoint::Watch(comparison, rhs) => write!(f, "x {} {}", comparison, rhs),
+        }
+    }
+}
+
+enum DebugCommand {
+    Step(usize),
+    Continue,
+    Break(Breakpoint),
+    Print(String),
+    Quit,
+}
+
+fn parse_command(line: &str) -> Option<DebugCommand> {
+    let words: Vec<_> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["step"]                    => Some(DebugCommand::Step(1)),
+        ["step", count]             => count.parse().ok().map(DebugCommand::Step),
+        ["continue"]                => Some(DebugCommand::Continue),
+        ["break", "cycle", cycle]   => cycle.parse().ok().map(|cycle| DebugCommand::Break(Breakpoint::Cycle(cycle))),
+        ["break", "x", op, value]   => Comparison::parse(op).zip(value.parse().ok())
+            .map(|(comparison, rhs)| DebugCommand::Break(Breakpoint::Watch(comparison, rhs))),
+        ["print", target]           => Some(DebugCommand::Print(target.to_string())),
+        ["quit"]                    => Some(DebugCommand::Quit),
+        _                           => None,
+    }
+}
+
+struct Debugger<'a> {
+    cpu: Cpu<'a>,
+    breakpoints: Vec<Breakpoint>,
+    current: Option<TraceEntry<'a>>,
+}
+
+impl<'a> Debugger<'a> {
+
+    fn new(program: &'a [Box<dyn Instruction>]) -> Self {
+        Self { cpu: Cpu::new(program), breakpoints: Vec::new(), current: None }
+    }
+
+    fn step(&mut self) -> Option<TraceEntry<'a>> {
+        let entry = self.cpu.next()?;
+        self.current = Some(entry);
+        Some(entry)
+    }
+
+    fn resume(&mut self) -> Option<Breakpoint> {
+        loop {
+            let previous_x = self.current.map(|entry| entry.x);
+            let entry = self.step()?;
+            if let Some(&breakpoint) = self.breakpoints.iter().find(|bp| bp.triggered(&entry, previous_x)) {
+                return Some(breakpoint);
+            }
+        }
+    }
+
+    fn inspect(&self, target: &str) -> Option<String> {
+        match target {
+            "x"           => Some(self.current.map_or(self.cpu.registers.x, |entry| entry.x).to_string()),
+            "pc"          => Some(self.cpu.registers.pc.to_string()),
+            "cycle"       => Some(self.cpu.cycle.to_string()),
+            "instruction" => Some(self.current.map_or("-".to_string(), |entry| entry.instruction.to_string())),
+            _             => None,
+        }
+    }
+}
+
+fn debug_session(program: &[Box<dyn Instruction>]) {
+    let mut debugger = Debugger::new(program);
+    let prompt = || { print!("(cpu) "); io::stdout().flush().expect("error writing to stdout"); };
+
+    prompt();
+    for line in io::stdin().lock().lines() {
+        let line = line.expect("error reading from stdin");
+        match parse_command(&line) {
+            Some(DebugCommand::Step(count)) => {
+                for _ in 0..count {
+                    match debugger.step() {
+                        Some(entry) => println!("{}", entry),
+                        None        => { println!("program finished"); break; },
+                    }
+                }
+            },
+            Some(DebugCommand::Continue) => match debugger.resume() {
+                Some(breakpoint) => println!("stopped at {} ({})", debugger.current.unwrap(), breakpoint),
+                None             => println!("program finished"),
+            },
+            Some(DebugCommand::Break(breakpoint)) => {
+                debugger.breakpoints.push(breakpoint);
+                println!("breakpoint {}: {}", debugger.breakpoints.len(), breakpoint);
+            },
+            Some(DebugCommand::Print(target)) => match debugger.inspect(&target) {
+                Some(value) => println!("{} = {}", target, value),
+                None        => println!("unknown target `{}`", target),
+            },
+            Some(DebugCommand::Quit) => break,
+            None if line.trim().is_empty() => {},
+            None => println!("unrecognised command `{}`", line.trim()),
         }
+        prompt();
     }
+}
+
+#[derive(Debug)]
+enum CrtError {
+    EmptySprite,
+    SampleAtCycleZero,
+    ZeroInterval,
+}
 
-    fn apply_to(&self, state: &mut isize) {
+impl fmt::Display for CrtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Instruction::Addx(amount) => *state += amount,
-            Instruction::Noop         => {},
+            CrtError::EmptySprite       => write!(f, "the sprite must be at least one pixel wide"),
+            CrtError::SampleAtCycleZero => write!(f, "cycles are numbered from 1, so sampling cannot start at cycle 0"),
+            CrtError::ZeroInterval      => write!(f, "the sampling interval must be at least one cycle"),
         }
     }
 }
 
+#[derive(Clone, Copy)]
+struct SamplingSchedule {
+    first_cycle: usize,
+    interval: usize,
+    samples: usize,
+}
+
+struct Crt {
+    width: usize,
+    height: usize,
+    sprite_width: usize,
+    sampling: SamplingSchedule,
+}
+
+impl Default for Crt {
+    fn default() -> Self {
+        Self::new(40, 6, 3, SamplingSchedule { first_cycle: 20, interval: 40, samples: 6 }).expect("default CRT is valid")
+    }
+}
+
+impl Crt {
+
+    fn new(width: usize, height: usize, sprite_width: usize, sampling: SamplingSchedule) -> Result<Self, CrtError> {
+        if sprite_width == 0 { return Err(CrtError::EmptySprite); }
+        if sampling.first_cycle == 0 { return Err(CrtError::SampleAtCycleZero); }
+        if sampling.interval == 0 { return Err(CrtError::ZeroInterval); }
+        Ok(Self { width, height, sprite_width, sampling })
+    }
+
+    fn signal_strength<'a, I: Iterator<Item = TraceEntry<'a>>>(&self, trace: I) -> isize {
+        let SamplingSchedule { first_cycle, interval, samples } = self.sampling;
+        trace.skip(first_cycle - 1).step_by(interval).take(samples)
+            .map(|entry| (entry.cycle as isize) * entry.x)
+            .sum()
+    }
+
+    fn is_lit(&self, sprite_position: isize, pixel: usize) -> bool {
+        let offset = pixel as isize - sprite_position;
+        let reach = (self.sprite_width as isize - 1) / 2;
+        -reach <= offset && offset <= self.sprite_width as isize / 2
+    }
+
+    fn frame<I: Iterator<Item = isize>>(&self, sprite_positions: I) -> Vec<Vec<bool>> {
+        let mut frame = vec![vec![false; self.width]; self.height];
+        for (idx, sprite_position) in sprite_positions.take(self.width * self.height).enumerate() {
+            let (row, col) = (idx / self.width, idx % self.width);
+            frame[row][col] = self.is_lit(sprite_position, col);
+        }
+        frame
+    }
+
+    fn frame_text(frame: &[Vec<bool>]) -> String {
+        frame.iter().map(|row| row.iter().map(|&lit| if lit { '#' } else { ' ' }).collect::<String>()).collect::<Vec<_>>().join("\n")
+    }
+}
+
 fn read_lines() -> impl Iterator<Item = String> {
     let file = File::open("inputs/10.txt").expect("input file not present");
     BufReader::new(file).lines().map(|l| l.expect("error reading from file"))
 }
 
-fn parse_line(line: &str) -> Instruction {
-    match &line[0..4] {
-        "addx" => Instruction::Addx(str::parse(&line[5..]).expect("could not parse integer")),
-        "noop" => Instruction::Noop,
-        _ => panic!("unexpected instruction"),
-    }
+fn parse_line(line_number: usize, line: &str, instruction_set: &HashMap<&'static str, Decoder>) -> Result<Box<dyn Instruction>, ProgramError> {
+    let words: Vec<_> = line.split_whitespace().collect();
+    let (opcode, operands) = words.split_first().ok_or(ProgramError::UnknownOpcode { line: line_number, opcode: String::new() })?;
+    let decoder = instruction_set.get(opcode).ok_or_else(|| ProgramError::UnknownOpcode { line: line_number, opcode: opcode.to_string() })?;
+    decoder(operands).ok_or_else(|| ProgramError::BadOperands { line: line_number, text: line.to_string() })
 }
 
-fn cycle_values(program: &[Instruction]) -> impl Iterator<Item = isize> + '_ {
-    program.iter().scan(1, |state, instr| {
-        let values = repeat(*state).take(instr.cycles());
-        instr.apply_to(state);
-        Some(values)
-    }).flatten()
+fn parse_program<I: Iterator<Item = String>>(lines: I) -> Result<Program, ProgramError> {
+    let instruction_set = instruction_set();
+    lines.enumerate().map(|(idx, line)| parse_line(idx + 1, &line, &instruction_set)).collect()
 }
 
-fn crt_line<I: Iterator<Item = isize>>(sprite_positions: &mut I) -> Option<String> {
-    let output: String = sprite_positions.take(40).enumerate().map(|(idx, sprite_position)| {
-        if (sprite_position - (idx as isize)).abs() <= 1 { '#' } else { ' ' }
-    }).collect();
-    if output.is_empty() { None } else { Some(output) }
+fn main() {
+    let program = parse_program(read_lines()).unwrap_or_else(|err| panic!("{}", err));
+    if env::args().nth(1).as_deref() == Some("debug") {
+        debug_session(&program);
+        return;
+    }
+
+    let crt = Crt::default();
+    println!("Part 1: {}", crt.signal_strength(Cpu::new(&program)));
+
+    let frame = crt.frame(Cpu::new(&program).map(|entry| entry.x));
+    println!("Part 2:\n{}", Crt::frame_text(&frame));
 }
 
-fn main() {
-    let program: Vec<_> = read_lines().map(|l| parse_line(&l)).collect();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEDULE: SamplingSchedule = SamplingSchedule { first_cycle: 1, interval: 1, samples: 3 };
 
-    let signal_strengths = cycle_values(&program).enumerate().map(|(idx, value)| ((idx + 1) as isize) * value);
-    println!("Part 1: {}", signal_strengths.skip(19).step_by(40).take(6).sum::<isize>());
+    #[test]
+    fn degenerate_crts_are_rejected() {
+        assert!(matches!(Crt::new(40, 6, 0, SCHEDULE), Err(CrtError::EmptySprite)));
+        assert!(matches!(Crt::new(40, 6, 3, SamplingSchedule { first_cycle: 0, ..SCHEDULE }), Err(CrtError::SampleAtCycleZero)));
+        assert!(matches!(Crt::new(40, 6, 3, SamplingSchedule { interval: 0, ..SCHEDULE }), Err(CrtError::ZeroInterval)));
+    }
 
-    let mut sprite_positions = cycle_values(&program);
-    println!("Part 2:");
-    while let Some(line) = crt_line(&mut sprite_positions) {
-        println!("{}", line);
+    #[test]
+    fn sampling_and_sprite_follow_the_schedule() {
+        let program = parse_program(["noop", "addx 3", "noop"].into_iter().map(str::to_string)).unwrap();
+        let crt = Crt::new(4, 1, 1, SCHEDULE).unwrap();
+        assert_eq!(crt.signal_strength(Cpu::new(&program)), 1 + 2 + 3);
+        assert_eq!(crt.frame([1, 1, 3, 3].into_iter()), vec![vec![false, true, false, true]]);
     }
 }
@@ -3,15 +3,218 @@ use itertools::Itertools;
 use nom::{
     branch::alt,
     bytes::complete::tag,
-    character::complete::{digit1, line_ending, space0},
-    combinator::{map, map_res, opt, recognize, value},
-    multi::{many0, separated_list0},
-    sequence::{delimited, preceded, tuple},
+    character::complete::{char, digit1, line_ending, one_of, space0},
+    combinator::{eof, map, map_res, opt, peek, recognize, value},
+    multi::{fold_many0, separated_list0},
+    sequence::{delimited, pair, preceded, terminated, tuple},
     IResult, Parser, error::ParseError,
 };
 
+use std::cmp::Ordering;
+use std::env;
+use std::fmt;
 use std::fs;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+#[derive(Clone, Copy, Debug)]
+enum ArithmeticError {
+    Overflow,
+    Negative,
+    DivisionByZero,
+    NotModular,
+}
+
+#[derive(Debug)]
+struct WorryError {
+    round: usize,
+    monkey: usize,
+    error: ArithmeticError,
+}
+
+impl fmt::Display for WorryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let problem = match self.error {
+            ArithmeticError::Overflow       => "overflowed",
+            ArithmeticError::Negative       => "became negative",
+            ArithmeticError::DivisionByZero => "was divided by zero",
+            ArithmeticError::NotModular     => "cannot be reduced modulo the tests",
+        };
+        write!(f, "round {}, monkey {}: worry level {}", self.round, self.monkey, problem)
+    }
+}
+
+trait Worry: Clone + Eq + Hash + fmt::Display {
+    fn from_usize(value: usize) -> Self;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_sub(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn checked_div(&self, other: &Self) -> Option<Self>;
+    fn rem_usize(&self, modulus: usize) -> usize;
+}
+
+macro_rules! primitive_worry {
+    ($t:ty) => {
+        impl Worry for $t {
+            fn from_usize(value: usize) -> Self { value as $t }
+            fn checked_add(&self, other: &Self) -> Option<Self> { <$t>::checked_add(*self, *other) }
+            fn checked_sub(&self, other: &Self) -> Option<Self> { <$t>::checked_sub(*self, *other) }
+            fn checked_mul(&self, other: &Self) -> Option<Self> { <$t>::checked_mul(*self, *other) }
+            fn checked_div(&self, other: &Self) -> Option<Self> { <$t>::checked_div(*self, *other) }
+            fn rem_usize(&self, modulus: usize) -> usize { (*self % modulus as $t) as usize }
+        }
+    };
+}
+
+primitive_worry!(usize);
+primitive_worry!(u128);
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+struct BigUint {
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+
+    fn zero() -> Self {
+        Self { limbs: Vec::new() }
+    }
+
+    fn normalised(mut limbs: Vec<u32>) -> Self {
+        while limbs.last() == Some(&0) { limbs.pop(); }
+        Self { limbs }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    fn bits(&self) -> usize {
+        self.limbs.last().map_or(0, |top| 32 * self.limbs.len() - top.leading_zeros() as usize)
+    }
+
+    fn bit(&self, idx: usize) -> bool {
+        self.limbs.get(idx / 32).is_some_and(|limb| limb >> (idx % 32) & 1 == 1)
+    }
+
+    fn shifted_left_with(&self, low_bit: bool) -> Self {
+        let mut limbs = Vec::with_capacity(self.limbs.len() + 1);
+        let mut carry = low_bit as u32;
+        for &limb in &self.limbs {
+            limbs.push(limb << 1 | carry);
+            carry = limb >> 31;
+        }
+        limbs.push(carry);
+        Self::normalised(limbs)
+    }
+
+    fn div_rem_small(&self, divisor: u32) -> (Self, u32) {
+        let mut quotient = vec![0; self.limbs.len()];
+        let mut remainder = 0u64;
+        for (idx, &limb) in self.limbs.iter().enumerate().rev() {
+            let current = remainder << 32 | limb as u64;
+            quotient[idx] = (current / divisor as u64) as u32;
+            remainder = current % divisor as u64;
+        }
+        (Self::normalised(quotient), remainder as u32)
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.limbs.len().cmp(&other.limbs.len()).then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() { return write!(f, "0"); }
+
+        let mut chunks = Vec::new();
+        let mut value = self.clone();
+        while !value.is_zero() {
+            let (quotient, chunk) = value.div_rem_small(1_000_000_000);
+            chunks.push(chunk);
+            value = quotient;
+        }
+
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() { write!(f, "{:09}", chunk)?; }
+        Ok(())
+    }
+}
+
+impl Worry for BigUint {
+    fn from_usize(value: usize) -> Self {
+        let value = value as u64;
+        Self::normalised(vec![value as u32, (value >> 32) as u32])
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        let mut limbs = Vec::with_capacity(self.limbs.len().max(other.limbs.len()) + 1);
+        let mut carry = 0u64;
+        for idx in 0..self.limbs.len().max(other.limbs.len()) {
+            let sum = *self.limbs.get(idx).unwrap_or(&0) as u64 + *other.limbs.get(idx).unwrap_or(&0) as u64 + carry;
+            limbs.push(sum as u32);
+            carry = sum >> 32;
+        }
+        limbs.push(carry as u32);
+        Some(Self::normalised(limbs))
+    }
+
+    fn checked_sub(&self, other: &Self) -> Option<Self> {
+        if self < other { return None; }
+
+        let mut limbs = Vec::with_capacity(self.limbs.len());
+        let mut borrow = 0i64;
+        for (idx, &limb) in self.limbs.iter().enumerate() {
+            let mut difference = limb as i64 - *other.limbs.get(idx).unwrap_or(&0) as i64 - borrow;
+            borrow = if difference < 0 { difference += 1 << 32; 1 } else { 0 };
+            limbs.push(difference as u32);
+        }
+        Some(Self::normalised(limbs))
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        let mut limbs = vec![0u32; self.limbs.len() + other.limbs.len()];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.limbs.iter().enumerate() {
+                let product = a as u64 * b as u64 + limbs[i + j] as u64 + carry;
+                limbs[i + j] = product as u32;
+                carry = product >> 32;
+            }
+            limbs[i + other.limbs.len()] = carry as u32;
+        }
+        Some(Self::normalised(limbs))
+    }
+
+    fn checked_div(&self, other: &Self) -> Option<Self> {
+        if other.is_zero() { return None; }
+        if let [divisor] = other.limbs[..] { return Some(self.div_rem_small(divisor).0); }
+
+        let mut quotient = vec![0u32; self.limbs.len()];
+        let mut remainder = BigUint::zero();
+        for idx in (0..self.bits()).rev() {
+            remainder = remainder.shifted_left_with(self.bit(idx));
+            if remainder >= *other {
+                remainder = remainder.checked_sub(other).unwrap();
+                quotient[idx / 32] |= 1 << (idx % 32);
+            }
+        }
+        Some(Self::normalised(quotient))
+    }
+
+    fn rem_usize(&self, modulus: usize) -> usize {
+        self.limbs.iter().rev().fold(0u128, |remainder, &limb| (remainder << 32 | limb as u128) % modulus as u128) as usize
+    }
+}
 
 #[derive(Clone)]
 struct Monkey {
@@ -22,17 +225,56 @@ struct Monkey {
 
 #[derive(Clone)]
 enum Operation {
-    Add(usize),
-    Multiply(usize),
-    Square,
+    Old,
+    Literal(usize),
+    Add(Box<Operation>, Box<Operation>),
+    Subtract(Box<Operation>, Box<Operation>),
+    Multiply(Box<Operation>, Box<Operation>),
+    Divide(Box<Operation>, Box<Operation>),
 }
 
 impl Operation {
-    fn apply_to(&self, item: usize) -> usize {
+    fn binary(operator: char, lhs: Operation, rhs: Operation) -> Operation {
+        let (lhs, rhs) = (Box::new(lhs), Box::new(rhs));
+        match operator {
+            '+' => Operation::Add(lhs, rhs),
+            '-' => Operation::Subtract(lhs, rhs),
+            '*' => Operation::Multiply(lhs, rhs),
+            '/' => Operation::Divide(lhs, rhs),
+            _   => unreachable!("unexpected operator"),
+        }
+    }
+
+    fn apply_to<W: Worry>(&self, item: &W) -> Result<W, ArithmeticError> {
+        match self {
+            Operation::Old                 => Ok(item.clone()),
+            Operation::Literal(value)      => Ok(W::from_usize(*value)),
+            Operation::Add(lhs, rhs)       => lhs.apply_to(item)?.checked_add(&rhs.apply_to(item)?).ok_or(ArithmeticError::Overflow),
+            Operation::Subtract(lhs, rhs)  => lhs.apply_to(item)?.checked_sub(&rhs.apply_to(item)?).ok_or(ArithmeticError::Negative),
+            Operation::Multiply(lhs, rhs)  => lhs.apply_to(item)?.checked_mul(&rhs.apply_to(item)?).ok_or(ArithmeticError::Overflow),
+            Operation::Divide(lhs, rhs)    => lhs.apply_to(item)?.checked_div(&rhs.apply_to(item)?).ok_or(ArithmeticError::DivisionByZero),
+        }
+    }
+
+    fn apply_modulo(&self, item: usize, modulus: usize) -> Result<usize, ArithmeticError> {
+        self.evaluate_modulo(item as u128 % modulus as u128, modulus as u128).map(|value| value as usize)
+    }
+
+    fn evaluate_modulo(&self, item: u128, modulus: u128) -> Result<u128, ArithmeticError> {
+        match self {
+            Operation::Old                 => Ok(item),
+            Operation::Literal(value)      => Ok(*value as u128 % modulus),
+            Operation::Add(lhs, rhs)       => Ok((lhs.evaluate_modulo(item, modulus)? + rhs.evaluate_modulo(item, modulus)?) % modulus),
+            Operation::Multiply(lhs, rhs)  => Ok((lhs.evaluate_modulo(item, modulus)? * rhs.evaluate_modulo(item, modulus)?) % modulus),
+            Operation::Subtract(_, _) | Operation::Divide(_, _) => Err(ArithmeticError::NotModular),
+        }
+    }
+
+    fn is_modular(&self) -> bool {
         match self {
-            Operation::Add(amount)      => item + amount,
-            Operation::Multiply(amount) => item * amount,
-            Operation::Square           => item * item,
+            Operation::Old | Operation::Literal(_) => true,
+            Operation::Add(lhs, rhs) | Operation::Multiply(lhs, rhs) => lhs.is_modular() && rhs.is_modular(),
+            Operation::Subtract(_, _) | Operation::Divide(_, _) => false,
         }
     }
 }
@@ -45,8 +287,8 @@ struct Test {
 }
 
 impl Test {
-    fn get_target(&self, item: usize) -> usize {
-        if item % self.modulus == 0 { self.true_target } else { self.false_target }
+    fn get_target<W: Worry>(&self, item: &W) -> usize {
+        if item.rem_usize(self.modulus) == 0 { self.true_target } else { self.false_target }
     }
 }
 
@@ -55,11 +297,36 @@ enum WorryManager {
     ModBy(usize),
 }
 
+#[derive(Debug)]
+enum ModularError {
+    NotModular { monkey: usize },
+    ModulusOverflow,
+}
+
+impl fmt::Display for ModularError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModularError::NotModular { monkey } =>
+                write!(f, "the operation of monkey {} uses subtraction or division, so worry levels cannot be reduced modulo the tests", monkey),
+            ModularError::ModulusOverflow =>
+                write!(f, "the least common multiple of the test moduli does not fit in a usize"),
+        }
+    }
+}
+
 impl WorryManager {
-    fn apply_to(&self, item: usize) -> usize {
+    fn modular_for(monkeys: &[Monkey]) -> Result<WorryManager, ModularError> {
+        match monkeys.iter().position(|monkey| !monkey.operation.is_modular()) {
+            Some(monkey) => Err(ModularError::NotModular { monkey }),
+            None         => modulus(monkeys).map(WorryManager::ModBy).ok_or(ModularError::ModulusOverflow),
+        }
+    }
+
+    fn update<W: Worry>(&self, operation: &Operation, item: &W) -> Result<W, ArithmeticError> {
         match self {
-            WorryManager::DivideBy(amount) => item / amount,
-            WorryManager::ModBy(amount)    => item % amount,
+            WorryManager::DivideBy(amount) => operation.apply_to(item)?.checked_div(&W::from_usize(*amount)).ok_or(ArithmeticError::DivisionByZero),
+            WorryManager::ModBy(0)         => Err(ArithmeticError::DivisionByZero),
+            WorryManager::ModBy(amount)    => Ok(W::from_usize(operation.apply_modulo(item.rem_usize(*amount), *amount)?)),
         }
     }
 }
@@ -82,20 +349,30 @@ fn starting_items(input: &str) -> IResult<&str, Vec<usize>> {
     on_line(preceded(tag("Starting items: "), separated_list0(tag(", "), number)))(input)
 }
 
-fn add_operation(input: &str) -> IResult<&str, Operation> {
-    map(preceded(tag("new = old + "), number), Operation::Add)(input)
+fn ws<'a, O, E: ParseError<&'a str>, F: Parser<&'a str, O, E>>(f: F) -> impl FnMut(&'a str) -> IResult<&'a str, O, E> {
+    delimited(space0, f, space0)
 }
 
-fn multiply_operation(input: &str) -> IResult<&str, Operation> {
-    map(preceded(tag("new = old * "), number), Operation::Multiply)(input)
+fn factor(input: &str) -> IResult<&str, Operation> {
+    ws(alt((
+        value(Operation::Old, tag("old")),
+        map(number, Operation::Literal),
+        delimited(char('('), expression, char(')')),
+    )))(input)
 }
 
-fn square_operation(input: &str) -> IResult<&str, Operation> {
-    value(Operation::Square, tag("new = old * old"))(input)
+fn term(input: &str) -> IResult<&str, Operation> {
+    let (input, first) = factor(input)?;
+    fold_many0(pair(one_of("*/"), factor), move || first.clone(), |lhs, (op, rhs)| Operation::binary(op, lhs, rhs))(input)
+}
+
+fn expression(input: &str) -> IResult<&str, Operation> {
+    let (input, first) = term(input)?;
+    fold_many0(pair(one_of("+-"), term), move || first.clone(), |lhs, (op, rhs)| Operation::binary(op, lhs, rhs))(input)
 }
 
 fn operation(input: &str) -> IResult<&str, Operation> {
-    on_line(preceded(tag("Operation: "), alt((add_operation, multiply_operation, square_operation))))(input)
+    on_line(preceded(tag("Operation: new ="), terminated(expression, peek(alt((line_ending, eof))))))(input)
 }
 
 fn test_modulus(input: &str) -> IResult<&str, usize> {
@@ -120,8 +397,19 @@ fn monkey(input: &str) -> IResult<&str, Monkey> {
     })(input)
 }
 
-fn parse(input: &str) -> Vec<Monkey> {
-    many0(on_line(monkey))(input).expect("failed to parse").1
+fn parse(input: &str) -> Result<Vec<Monkey>, MonkeyParseError> {
+    let (mut monkeys, mut remaining) = (Vec::new(), input);
+    while !remaining.trim().is_empty() {
+        match on_line(monkey)(remaining) {
+            Ok((rest, monkey)) => { monkeys.push(monkey); remaining = rest; },
+            Err(nom::Err::Error(err) | nom::Err::Failure(err)) => {
+                let line = input[..input.len() - err.input.len()].matches('\n').count() + 1;
+                return Err(MonkeyParseError { monkey: monkeys.len(), line });
+            },
+            Err(nom::Err::Incomplete(_)) => unreachable!(),
+        }
+    }
+    Ok(monkeys)
 }
 
 // END NOM PARSING CODE
@@ -130,40 +418,358 @@ fn read_data() -> String {
     fs::read_to_string("inputs/11.txt").expect("unable to read input file")
 }
 
-fn take_turn(monkeys: &mut [Monkey], index: usize, worry_manager: &WorryManager) {
-    while let Some(mut item) = monkeys[index].items.pop_front() {
-        item = worry_manager.apply_to(monkeys[index].operation.apply_to(item));
-        let target = monkeys[index].test.get_target(item);
-        monkeys[target].items.push_back(item);
+fn take_turn<W: Worry>(monkeys: &[Monkey], items: &mut [VecDeque<W>], index: usize, worry_manager: &WorryManager) -> Result<(), ArithmeticError> {
+    while let Some(mut item) = items[index].pop_front() {
+        item = worry_manager.update(&monkeys[index].operation, &item)?;
+        let target = monkeys[index].test.get_target(&item);
+        items[target].push_back(item);
     }
+    Ok(())
 }
 
-fn modulus(monkeys: &[Monkey]) -> usize {
-    monkeys.iter().map(|monkey| monkey.test.modulus).product()
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
 }
 
-fn inspection_counts(monkeys: &mut [Monkey], rounds: usize, worry_manager: &WorryManager) -> Vec<usize> {
+fn modulus(monkeys: &[Monkey]) -> Option<usize> {
+    monkeys.iter().map(|monkey| monkey.test.modulus).try_fold(1usize, |lcm, modulus| (lcm / gcd(lcm, modulus)).checked_mul(modulus))
+}
+
+fn inspection_counts<W: Worry>(monkeys: &[Monkey], rounds: usize, worry_manager: &WorryManager) -> Result<Vec<usize>, WorryError> {
+    let mut items: Vec<VecDeque<W>> = monkeys.iter().map(|monkey| monkey.items.iter().map(|&item| W::from_usize(item)).collect()).collect();
     let mut inspection_counts = vec![0; monkeys.len()];
-    for _ in 0..rounds {
+    for round in 1..=rounds {
         for index in 0..monkeys.len() {
-            inspection_counts[index] += monkeys[index].items.len();
-            take_turn(monkeys, index, worry_manager);
+            inspection_counts[index] += items[index].len();
+            take_turn(monkeys, &mut items, index, worry_manager).map_err(|error| WorryError { round, monkey: index, error })?;
+        }
+    }
+    Ok(inspection_counts)
+}
+
+struct ItemTrajectory {
+    rounds: Vec<Vec<usize>>,
+    cycle_start: usize,
+}
+
+impl ItemTrajectory {
+
+    fn trace(monkeys: &[Monkey], monkey: usize, item: usize, worry_manager: &WorryManager) -> Result<Self, WorryError> {
+        let mut seen = HashMap::new();
+        let mut rounds = Vec::new();
+        let mut state = (monkey, item);
+
+        while !seen.contains_key(&state) {
+            seen.insert(state, rounds.len());
+            let (mut monkey, mut item) = state;
+            let mut inspections = Vec::new();
+            loop {
+                inspections.push(monkey);
+                item = worry_manager.update(&monkeys[monkey].operation, &item)
+                    .map_err(|error| WorryError { round: rounds.len() + 1, monkey, error })?;
+                let target = monkeys[monkey].test.get_target(&item);
+                let round_over = target <= monkey;
+                monkey = target;
+                if round_over { break; }
+            }
+            rounds.push(inspections);
+            state = (monkey, item);
         }
+
+        Ok(Self { rounds, cycle_start: seen[&state] })
+    }
+
+    fn round(&self, round: usize) -> &[usize] {
+        let cycle_length = self.rounds.len() - self.cycle_start;
+        let idx = if round < self.rounds.len() { round } else { self.cycle_start + (round - self.cycle_start) % cycle_length };
+        &self.rounds[idx]
     }
+
+    #[allow(dead_code)]
+    fn path(&self, rounds: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..rounds).flat_map(move |round| self.round(round).iter().map(move |&monkey| (round, monkey)))
+    }
+
+    fn add_inspection_counts(&self, counts: &mut [usize], rounds: usize) {
+        let mut tally = |range: std::ops::Range<usize>, times: usize| {
+            for monkey in range.flat_map(|round| self.round(round).iter()) { counts[*monkey] += times; }
+        };
+
+        tally(0..rounds.min(self.cycle_start), 1);
+        if rounds > self.cycle_start {
+            let cycle_length = self.rounds.len() - self.cycle_start;
+            let (cycles, remainder) = ((rounds - self.cycle_start) / cycle_length, (rounds - self.cycle_start) % cycle_length);
+            tally(self.cycle_start..self.rounds.len(), cycles);
+            tally(self.cycle_start..self.cycle_start + remainder, 1);
+        }
+    }
+}
+
+fn item_trajectories(monkeys: &[Monkey], worry_manager: &WorryManager) -> Result<Vec<ItemTrajectory>, WorryError> {
+    monkeys.iter().enumerate()
+        .flat_map(|(index, monkey)| monkey.items.iter().map(move |&item| (index, item)))
+        .map(|(index, item)| ItemTrajectory::trace(monkeys, index, item, worry_manager))
+        .collect()
+}
+
+fn fast_inspection_counts(trajectories: &[ItemTrajectory], monkey_count: usize, rounds: usize) -> Vec<usize> {
+    let mut inspection_counts = vec![0; monkey_count];
+    for trajectory in trajectories { trajectory.add_inspection_counts(&mut inspection_counts, rounds); }
     inspection_counts
 }
 
+#[derive(Debug)]
+struct MonkeyParseError {
+    monkey: usize,
+    line: usize,
+}
+
+impl fmt::Display for MonkeyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "could not parse monkey {} at line {}", self.monkey, self.line)
+    }
+}
+
+#[derive(Debug)]
+enum TargetError {
+    MissingTarget { monkey: usize, target: usize },
+    SelfTarget { monkey: usize },
+    ZeroModulus { monkey: usize },
+}
+
+impl fmt::Display for TargetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TargetError::MissingTarget { monkey, target } => write!(f, "monkey {} throws to monkey {}, which does not exist", monkey, target),
+            TargetError::SelfTarget { monkey } => write!(f, "monkey {} throws to itself", monkey),
+            TargetError::ZeroModulus { monkey } => write!(f, "monkey {} tests divisibility by zero", monkey),
+        }
+    }
+}
+
+fn targets(monkey: &Monkey) -> [usize; 2] {
+    [monkey.test.true_target, monkey.test.false_target]
+}
+
+fn validate_targets(monkeys: &[Monkey]) -> Result<(), TargetError> {
+    for (index, monkey) in monkeys.iter().enumerate() {
+        if monkey.test.modulus == 0 { return Err(TargetError::ZeroModulus { monkey: index }); }
+        for target in targets(monkey) {
+            if target >= monkeys.len() { return Err(TargetError::MissingTarget { monkey: index, target }); }
+            if target == index { return Err(TargetError::SelfTarget { monkey: index }); }
+        }
+    }
+    Ok(())
+}
+
+fn reachable_from(monkeys: &[Monkey], start: usize) -> Vec<bool> {
+    let mut reachable = vec![false; monkeys.len()];
+    let mut stack = vec![start];
+    while let Some(index) = stack.pop() {
+        if !reachable[index] {
+            reachable[index] = true;
+            stack.extend(targets(&monkeys[index]));
+        }
+    }
+    reachable
+}
+
+fn strongly_connected_components(monkeys: &[Monkey]) -> Vec<Vec<usize>> {
+    let reachability: Vec<_> = (0..monkeys.len()).map(|index| reachable_from(monkeys, index)).collect();
+    let mut assigned = vec![false; monkeys.len()];
+    let mut components = Vec::new();
+    for index in 0..monkeys.len() {
+        if assigned[index] { continue; }
+        let component: Vec<_> = (index..monkeys.len()).filter(|&other| reachability[index][other] && reachability[other][index]).collect();
+        for &member in &component { assigned[member] = true; }
+        components.push(component);
+    }
+    components
+}
+
+fn monkeys_never_receiving(monkeys: &[Monkey]) -> Vec<usize> {
+    (0..monkeys.len()).filter(|&index| !monkeys.iter().any(|monkey| targets(monkey).contains(&index))).collect()
+}
+
+fn non_coprime_moduli(monkeys: &[Monkey]) -> Vec<(usize, usize)> {
+    (0..monkeys.len()).tuple_combinations()
+        .filter(|&(a, b)| gcd(monkeys[a].test.modulus, monkeys[b].test.modulus) != 1)
+        .collect()
+}
+
+fn throw_graph_report(monkeys: &[Monkey]) -> String {
+    let mut report = vec!["Throw graph:".to_string()];
+    for (index, monkey) in monkeys.iter().enumerate() {
+        report.push(format!("  monkey {} -> {} (divisible by {}), {} (otherwise)", index, monkey.test.true_target, monkey.test.modulus, monkey.test.false_target));
+    }
+
+    let components = strongly_connected_components(monkeys).iter().map(|component| format!("{{{}}}", component.iter().join(", "))).join(" ");
+    report.push(format!("Strongly connected components: {}", components));
+
+    let never_receiving = monkeys_never_receiving(monkeys);
+    report.push(format!("Never receive items: {}", if never_receiving.is_empty() { "none".to_string() } else { never_receiving.iter().join(", ") }));
+
+    let shared_factors = non_coprime_moduli(monkeys);
+    let lcm = modulus(monkeys).map_or("overflow".to_string(), |lcm| lcm.to_string());
+    if shared_factors.is_empty() {
+        report.push(format!("Moduli are pairwise coprime; product = {}", lcm));
+    } else {
+        let pairs = shared_factors.iter().map(|(a, b)| format!("{} and {}", a, b)).join(", ");
+        report.push(format!("Moduli are not pairwise coprime (monkeys {}); LCM = {}", pairs, lcm));
+    }
+
+    report.join("\n")
+}
+
+fn throw_graph_dot(monkeys: &[Monkey]) -> String {
+    let mut dot = vec!["digraph monkeys {".to_string()];
+    for (index, monkey) in monkeys.iter().enumerate() {
+        dot.push(format!("    {} -> {} [label=\"divisible by {}\"];", index, monkey.test.true_target, monkey.test.modulus));
+        dot.push(format!("    {} -> {} [label=\"otherwise\"];", index, monkey.test.false_target));
+    }
+    dot.push("}".to_string());
+    dot.join("\n")
+}
+
 fn main() {
-    let starting_monkeys = parse(&read_data());
+    let starting_monkeys = parse(&read_data()).unwrap_or_else(|err| panic!("{}", err));
+    validate_targets(&starting_monkeys).unwrap_or_else(|err| panic!("{}", err));
+
+    match env::args().nth(1).as_deref() {
+        Some("report") => { println!("{}", throw_graph_report(&starting_monkeys)); return; },
+        Some("dot")    => { println!("{}", throw_graph_dot(&starting_monkeys)); return; },
+        _              => {},
+    }
 
-    let mut monkeys = starting_monkeys.clone();
-    let inspection_counts_part_1 = inspection_counts(&mut monkeys, 20, &WorryManager::DivideBy(3));
+    let inspection_counts_part_1 = inspection_counts::<BigUint>(&starting_monkeys, 20, &WorryManager::DivideBy(3))
+        .unwrap_or_else(|err| panic!("{}", err));
     let monkey_business: usize = inspection_counts_part_1.iter().sorted().rev().take(2).product();
     println!("Part 1: {}", monkey_business);
 
-    let mut monkeys = starting_monkeys.clone();
-    let modulus = modulus(&monkeys);
-    let inspection_counts_part_2 = inspection_counts(&mut monkeys, 10_000, &WorryManager::ModBy(modulus));
+    let worry_manager = WorryManager::modular_for(&starting_monkeys).unwrap_or_else(|err| panic!("{}", err));
+    let trajectories = item_trajectories(&starting_monkeys, &worry_manager).unwrap_or_else(|err| panic!("{}", err));
+    let inspection_counts_part_2 = fast_inspection_counts(&trajectories, starting_monkeys.len(), 10_000);
     let monkey_business: usize = inspection_counts_part_2.iter().sorted().rev().take(2).product();
     println!("Part 2: {}", monkey_business);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "Monkey 0:
+  Starting items: 79, 98
+  Operation: new = old * 19
+  Test: divisible by 23
+    If true: throw to monkey 2
+    If false: throw to monkey 3
+
+Monkey 1:
+  Starting items: 54, 65, 75, 74
+  Operation: new = old + 6
+  Test: divisible by 19
+    If true: throw to monkey 2
+    If false: throw to monkey 0
+
+Monkey 2:
+  Starting items: 79, 60, 97
+  Operation: new = old * old
+  Test: divisible by 13
+    If true: throw to monkey 1
+    If false: throw to monkey 3
+
+Monkey 3:
+  Starting items: 74
+  Operation: new = old + 3
+  Test: divisible by 17
+    If true: throw to monkey 0
+    If false: throw to monkey 1
+";
+
+    #[test]
+    fn fast_counts_match_brute_force() {
+        let monkeys = parse(EXAMPLE).unwrap();
+        let worry_manager = WorryManager::modular_for(&monkeys).unwrap();
+        let trajectories = item_trajectories(&monkeys, &worry_manager).unwrap();
+
+        for rounds in [0, 1, 20, 1000, 10_000] {
+            let brute_force = inspection_counts::<usize>(&monkeys, rounds, &worry_manager).unwrap();
+            assert_eq!(fast_inspection_counts(&trajectories, monkeys.len(), rounds), brute_force);
+
+            let mut from_paths = vec![0; monkeys.len()];
+            for (_, monkey) in trajectories.iter().flat_map(|trajectory| trajectory.path(rounds)) { from_paths[monkey] += 1; }
+            assert_eq!(from_paths, brute_force);
+        }
+        assert_eq!(fast_inspection_counts(&trajectories, monkeys.len(), 10_000), vec![52166, 47830, 1938, 52013]);
+    }
+
+    fn big(value: u128) -> BigUint {
+        BigUint::normalised((0..4).map(|limb| (value >> (32 * limb)) as u32).collect())
+    }
+
+    #[test]
+    fn u128_overflow_is_reported_with_round_and_monkey() {
+        let squaring = "Monkey 0:
+  Starting items: 2
+  Operation: new = old * old
+  Test: divisible by 1
+    If true: throw to monkey 1
+    If false: throw to monkey 1
+
+Monkey 1:
+  Starting items: 1
+  Operation: new = old * old
+  Test: divisible by 1
+    If true: throw to monkey 0
+    If false: throw to monkey 0
+";
+        let monkeys = parse(squaring).unwrap();
+        assert!(matches!(inspection_counts::<u128>(&monkeys, 4, &WorryManager::DivideBy(1)),
+            Err(WorryError { round: 4, monkey: 0, error: ArithmeticError::Overflow })));
+        assert!(inspection_counts::<u128>(&monkeys, 3, &WorryManager::DivideBy(1)).is_ok());
+        assert!(inspection_counts::<BigUint>(&monkeys, 6, &WorryManager::DivideBy(1)).is_ok());
+    }
+
+    #[test]
+    fn big_uint_arithmetic_matches_u128() {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = || { state ^= state << 13; state ^= state >> 7; state ^= state << 17; state };
+        for _ in 0..1000 {
+            let (a, b) = (next() as u128, next() as u128 >> (next() % 64));
+            let (wide, modulus) = (a * (next() as u128), (next() % 1_000_000_007) as usize + 1);
+
+            assert_eq!(big(a).checked_mul(&big(b)), Some(big(a * b)));
+            assert_eq!(big(a).checked_add(&big(b)), Some(big(a + b)));
+            assert_eq!(big(wide).checked_sub(&big(a)), wide.checked_sub(a).map(big));
+            assert_eq!(big(wide).checked_div(&big(b)), wide.checked_div(b).map(big));
+            assert_eq!(big(wide).checked_div(&big(wide >> 40)), wide.checked_div(wide >> 40).map(big));
+            assert_eq!(big(wide).rem_usize(modulus), (wide % modulus as u128) as usize);
+            assert_eq!(big(wide).to_string(), wide.to_string());
+        }
+    }
+
+    #[test]
+    fn zero_and_overflowing_moduli_are_rejected() {
+        let monkeys = parse(&EXAMPLE.replace("divisible by 19", "divisible by 0")).unwrap();
+        assert!(matches!(validate_targets(&monkeys), Err(TargetError::ZeroModulus { monkey: 1 })));
+
+        let mut monkeys = parse(EXAMPLE).unwrap();
+        for (monkey, modulus) in monkeys.iter_mut().zip([4_294_967_291, 4_294_967_279, 4_294_967_231, 4_294_967_197]) {
+            monkey.test.modulus = modulus;
+        }
+        assert!(matches!(WorryManager::modular_for(&monkeys), Err(ModularError::ModulusOverflow)));
+    }
+
+    #[test]
+    fn modular_reduction_rejects_subtraction_and_division() {
+        let monkeys = parse(&EXAMPLE.replace("old + 6", "old - 100")).unwrap();
+        assert!(matches!(WorryManager::modular_for(&monkeys), Err(ModularError::NotModular { monkey: 1 })));
+        assert!(matches!(inspection_counts::<u128>(&monkeys, 1, &WorryManager::DivideBy(1)),
+            Err(WorryError { round: 1, monkey: 1, error: ArithmeticError::Negative })));
+
+        let monkeys = parse(&EXAMPLE.replace("old * 19", "old / 2")).unwrap();
+        assert!(matches!(WorryManager::modular_for(&monkeys), Err(ModularError::NotModular { monkey: 0 })));
+        assert!(matches!(inspection_counts::<usize>(&monkeys, 1, &WorryManager::ModBy(96577)),
+            Err(WorryError { round: 1, monkey: 0, error: ArithmeticError::NotModular })));
+    }
+}
@@ -1,10 +1,70 @@
-use itertools::iproduct;
-use std::collections::VecDeque;
+use itertools::{iproduct, Itertools};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::env;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
 type Coordinate = (usize, usize);
 
+#[derive(Clone, Copy)]
+struct ClimbingRules {
+    max_climb: u8,
+    max_descent: Option<u8>,
+    diagonal: bool,
+    uphill_cost: usize,
+}
+
+impl Default for ClimbingRules {
+    fn default() -> Self {
+        Self { max_climb: 1, max_descent: None, diagonal: false, uphill_cost: 0 }
+    }
+}
+
+impl ClimbingRules {
+
+    fn allows(&self, from: u8, to: u8) -> bool {
+        to <= from.saturating_add(self.max_climb) && self.max_descent.is_none_or(|max_descent| from <= to.saturating_add(max_descent))
+    }
+
+    fn cost(&self, from: u8, to: u8) -> usize {
+        1 + self.uphill_cost * to.saturating_sub(from) as usize
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Origin {
+    Start,
+    AnyAtElevation(u8),
+}
+
+struct Configuration {
+    origin: Origin,
+    rules: ClimbingRules,
+}
+
+struct ShortestPaths {
+    distances: Vec<Vec<Option<usize>>>,
+    next_steps: Vec<Vec<Option<Coordinate>>>,
+}
+
+impl ShortestPaths {
+
+    fn distance(&self, coord: Coordinate) -> Option<usize> {
+        self.distances[coord.0][coord.1]
+    }
+
+    fn route_from(&self, start: Coordinate) -> Option<Vec<Coordinate>> {
+        self.distance(start)?;
+        let (mut route, mut current) = (vec![start], start);
+        while let Some(next) = self.next_steps[current.0][current.1] {
+            route.push(next);
+            current = next;
+        }
+        Some(route)
+    }
+}
+
 fn read_lines() -> impl Iterator<Item = String> {
     let file = File::open("inputs/12.txt").expect("input file not present");
     BufReader::new(file).lines().map(|l| l.expect("error reading from file"))
@@ -35,47 +95,83 @@ fn parse_map<I: Iterator<Item = String>>(input: I) -> (Vec<Vec<u8>>, Coordinate,
     (elevations, start, end)
 }
 
-fn neighbours(elevations: &[Vec<u8>], coord: Coordinate) -> Vec<Coordinate> {
+fn neighbours(elevations: &[Vec<u8>], coord: Coordinate, rules: &ClimbingRules) -> Vec<Coordinate> {
     let (row, col) = coord;
-    [(row.checked_sub(1), Some(col)), (row.checked_add(1), Some(col)), (Some(row), col.checked_sub(1)), (Some(row), col.checked_add(1))]
-        .iter()
-        .filter(|&(r, c)| r.is_some() && c.is_some() && r.unwrap() < elevations.len() && c.unwrap() < elevations[r.unwrap()].len())
-        .filter(|&(r, c)| elevations[row][col] <= elevations[r.unwrap()][c.unwrap()] + 1)
-        .map(|(r, c)| (r.unwrap(), c.unwrap()))
-        .collect()
+    let orthogonal = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+    let diagonal = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+    let offsets = if rules.diagonal { [&orthogonal[..], &diagonal[..]].concat() } else { orthogonal.to_vec() };
 
+    offsets.into_iter()
+        .filter_map(|(dr, dc)| Some((row.checked_add_signed(dr)?, col.checked_add_signed(dc)?)))
+        .filter(|&(r, c)| r < elevations.len() && c < elevations[r].len())
+        .filter(|&(r, c)| rules.allows(elevations[r][c], elevations[row][col]))
+        .collect()
 }
 
-fn calculate_minimum_distances(elevations: &[Vec<u8>], start: Coordinate) -> Vec<Vec<Option<usize>>> {
-    let mut queue = VecDeque::new();
+fn calculate_shortest_paths(elevations: &[Vec<u8>], goal: Coordinate, rules: &ClimbingRules) -> ShortestPaths {
+    let mut queue = BinaryHeap::new();
     let mut distances: Vec<Vec<Option<usize>>> = elevations.iter().map(|row| row.iter().map(|_| None).collect()).collect();
+    let mut next_steps: Vec<Vec<Option<Coordinate>>> = elevations.iter().map(|row| row.iter().map(|_| None).collect()).collect();
 
-    queue.push_back(start);
-    distances[start.0][start.1] = Some(0);
+    queue.push(Reverse((0, goal)));
+    distances[goal.0][goal.1] = Some(0);
 
-    while let Some(current_coordinate) = queue.pop_front() {
-        let current_distance = distances[current_coordinate.0][current_coordinate.1].unwrap();
-        for next_coordinate in neighbours(elevations, current_coordinate) {
-            if let None = distances[next_coordinate.0][next_coordinate.1] {
-                distances[next_coordinate.0][next_coordinate.1] = Some(current_distance + 1);
-                queue.push_back(next_coordinate);
+    while let Some(Reverse((current_distance, current_coordinate))) = queue.pop() {
+        if distances[current_coordinate.0][current_coordinate.1] != Some(current_distance) { continue; }
+        for next_coordinate in neighbours(elevations, current_coordinate, rules) {
+            let step_cost = rules.cost(elevations[next_coordinate.0][next_coordinate.1], elevations[current_coordinate.0][current_coordinate.1]);
+            let next_distance = current_distance + step_cost;
+            if distances[next_coordinate.0][next_coordinate.1].is_none_or(|distance| next_distance < distance) {
+                distances[next_coordinate.0][next_coordinate.1] = Some(next_distance);
+                next_steps[next_coordinate.0][next_coordinate.1] = Some(current_coordinate);
+                queue.push(Reverse((next_distance, next_coordinate)));
             }
         }
     }
 
-    distances
+    ShortestPaths { distances, next_steps }
+}
+
+fn solve(configuration: &Configuration, elevations: &[Vec<u8>], start: Coordinate, end: Coordinate) -> Option<(usize, Vec<Coordinate>)> {
+    let shortest_paths = calculate_shortest_paths(elevations, end, &configuration.rules);
+    let origin = match configuration.origin {
+        Origin::Start => start,
+        Origin::AnyAtElevation(elevation) => iproduct!(0..elevations.len(), 0..elevations[0].len())
+            .filter(|&(r, c)| elevations[r][c] == elevation)
+            .filter(|&coord| shortest_paths.distance(coord).is_some())
+            .min_by_key(|&coord| shortest_paths.distance(coord))?,
+    };
+    Some((shortest_paths.distance(origin)?, shortest_paths.route_from(origin)?))
+}
+
+fn render_route(elevations: &[Vec<u8>], route: &[Coordinate]) -> String {
+    let mut grid: Vec<Vec<char>> = elevations.iter().map(|row| row.iter().map(|_| '.').collect()).collect();
+    for (&(r1, c1), &(r2, c2)) in route.iter().tuple_windows() {
+        grid[r1][c1] = match (r2 as isize - r1 as isize, c2 as isize - c1 as isize) {
+            (0, 1)            => '>',
+            (0, -1)           => '<',
+            (-1, 0)           => '^',
+            (1, 0)            => 'v',
+            (-1, 1) | (1, -1) => '/',
+            _                 => '\\',
+        };
+    }
+    if let Some(&(r, c)) = route.last() { grid[r][c] = 'E'; }
+    grid.iter().map(|row| row.iter().collect::<String>()).join("\n")
 }
 
 fn main() {
     let (elevations, start, end) = parse_map(read_lines());
-    let minimum_distances = calculate_minimum_distances(&elevations, end);
 
-    let distance_from_start = minimum_distances[start.0][start.1].expect("goal is not reachable");
+    let part_1 = Configuration { origin: Origin::Start, rules: ClimbingRules::default() };
+    let (distance_from_start, route_from_start) = solve(&part_1, &elevations, start, end).expect("goal is not reachable");
     println!("Part 1: {}", distance_from_start);
 
-    let distance_from_low_elevation = iproduct!(0..elevations.len(), 0..elevations[0].len())
-        .filter(|&(r, c)| elevations[r][c] == 0)
-        .filter_map(|(r, c)| minimum_distances[r][c])
-        .min().expect("minimum elevation is not reachable");
+    let part_2 = Configuration { origin: Origin::AnyAtElevation(0), rules: ClimbingRules::default() };
+    let (distance_from_low_elevation, route_from_low_elevation) = solve(&part_2, &elevations, start, end).expect("minimum elevation is not reachable");
     println!("Part 2: {}", distance_from_low_elevation);
+
+    if env::args().nth(1).as_deref() == Some("route") {
+        println!("\n{}\n\n{}", render_route(&elevations, &route_from_start), render_route(&elevations, &route_from_low_elevation));
+    }
 }
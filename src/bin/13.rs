@@ -2,58 +2,336 @@ use itertools::Itertools;
 
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    character::complete::digit1,
-    combinator::{map, map_res, recognize},
+    bytes::complete::{escaped_transform, tag, take_while_m_n},
+    character::complete::{char, digit1, multispace0, none_of, one_of},
+    combinator::{map, map_opt, map_res, opt, recognize, value},
     multi::separated_list0,
-    sequence::delimited,
-    IResult,
+    sequence::{delimited, pair, preceded, separated_pair, tuple},
+    IResult, Parser, error::ParseError,
 };
 
 use std::cmp::Ordering;
+use std::env;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::slice;
+use std::str::FromStr;
 
+#[derive(Clone, Debug)]
 enum Packet {
-    Number(usize),
+    Number(isize),
     List(Vec<Packet>),
 }
 
-impl PartialEq for Packet {
-    fn eq(&self, other: &Packet) -> bool {
-        matches!(self.partial_cmp(other), Some(Ordering::Equal))
+impl Ord for Packet {
+    fn cmp(&self, other: &Packet) -> Ordering {
+        use Packet::*;
+        match (self, other) {
+            (Number(a), Number(b)) => a.cmp(b),
+            (Number(_), List(b))   => slice::from_ref(self).cmp(b.as_slice()),
+            (List(a), Number(_))   => a.as_slice().cmp(slice::from_ref(other)),
+            (List(a), List(b))     => a.cmp(b),
+        }
     }
 }
 
 impl PartialOrd for Packet {
     fn partial_cmp(&self, other: &Packet) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Packet {
+    fn eq(&self, other: &Packet) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Packet {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Rule {
+    IntegerComparison,
+    ListLength,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rule::IntegerComparison     => write!(f, "integer comparison"),
+            Rule::ListLength            => write!(f, "list length"),
+        }
+    }
+}
+
+struct Explanation {
+    ordering: Ordering,
+    path: Vec<usize>,
+    rule: Option<Rule>,
+    promoted: bool,
+}
+
+impl Packet {
+
+    fn explain(&self, other: &Packet) -> Explanation {
+        let mut path = Vec::new();
+        match self.explain_at(other, &mut path) {
+            Some((ordering, rule, promoted)) => Explanation { ordering, path, rule: Some(rule), promoted },
+            None                             => Explanation { ordering: Ordering::Equal, path, rule: None, promoted: false },
+        }
+    }
+
+    fn explain_at(&self, other: &Packet, path: &mut Vec<usize>) -> Option<(Ordering, Rule, bool)> {
         use Packet::*;
         match (self, other) {
-            (Number(a), Number(b)) => a.partial_cmp(b),
-            (Number(a), List(_b))  => List(vec![Number(*a)]).partial_cmp(other),
-            (List(_a), Number(b))  => self.partial_cmp(&List(vec![Number(*b)])),
-            (List(a), List(b))     => a.partial_cmp(b),
+            (Number(a), Number(b)) => a.cmp(b).is_ne().then(|| (a.cmp(b), Rule::IntegerComparison, false)),
+            (Number(_), List(b))   => Packet::explain_lists(slice::from_ref(self), b, path).map(|(ordering, rule, _)| (ordering, rule, true)),
+            (List(a), Number(_))   => Packet::explain_lists(a, slice::from_ref(other), path).map(|(ordering, rule, _)| (ordering, rule, true)),
+            (List(a), List(b))     => Packet::explain_lists(a, b, path),
+        }
+    }
+
+    fn explain_lists(a: &[Packet], b: &[Packet], path: &mut Vec<usize>) -> Option<(Ordering, Rule, bool)> {
+        for (idx, (x, y)) in a.iter().zip(b).enumerate() {
+            path.push(idx);
+            if let Some(decision) = x.explain_at(y, path) { return Some(decision); }
+            path.pop();
+        }
+        a.len().cmp(&b.len()).is_ne().then(|| (a.len().cmp(&b.len()), Rule::ListLength, false))
+    }
+
+    fn render_highlighting(&self, path: &[usize]) -> String {
+        let (mut text, mut span) = (String::new(), None);
+        self.write_tracking(path, &mut Vec::new(), &mut text, &mut span);
+        let (start, end) = span.unwrap_or((0, 0));
+        format!("{}\n{}{}", text, " ".repeat(start), "^".repeat(end - start))
+    }
+
+    fn write_tracking(&self, path: &[usize], current: &mut Vec<usize>, text: &mut String, span: &mut Option<(usize, usize)>) {
+        let start = text.len();
+        match self {
+            Packet::Number(n)     => text.push_str(&n.to_string()),
+            Packet::List(packets) => {
+                text.push('[');
+                for (idx, packet) in packets.iter().enumerate() {
+                    if idx > 0 { text.push(','); }
+                    current.push(idx);
+                    packet.write_tracking(path, current, text, span);
+                    current.pop();
+                }
+                text.push(']');
+            },
+        }
+        if span.is_none() && path.starts_with(current) { *span = Some((start, text.len())); }
+    }
+}
+
+impl Explanation {
+
+    fn render(&self, left: &Packet, right: &Packet) -> String {
+        let verdict = match self.ordering {
+            Ordering::Less    => "in the right order",
+            Ordering::Greater => "not in the right order",
+            Ordering::Equal   => "equal",
+        };
+        let reason = match self.rule {
+            Some(rule) if self.promoted => format!(", decided by {} at path {:?} after promoting an integer to a list", rule, self.path),
+            Some(rule)                  => format!(", decided by {} at path {:?}", rule, self.path),
+            None       => String::new(),
+        };
+        let show = |packet: &Packet| if self.rule.is_some() { packet.render_highlighting(&self.path) } else { packet.to_string() };
+        format!("{}\n{}\n{}{}", show(left), show(right), verdict, reason)
+    }
+}
+
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Packet::Number(n)     => write!(f, "{}", n),
+            Packet::List(packets) => write!(f, "[{}]", packets.iter().join(",")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null          => write!(f, "null"),
+            Json::Bool(b)       => write!(f, "{}", b),
+            Json::Integer(n)    => write!(f, "{}", n),
+            Json::Float(x)      => write!(f, "{:?}", x),
+            Json::String(s)     => write!(f, "{}", json_string_literal(s)),
+            Json::Array(values) => write!(f, "[{}]", values.iter().join(",")),
+            Json::Object(fields) => {
+                write!(f, "{{{}}}", fields.iter().map(|(key, value)| format!("{}:{}", json_string_literal(key), value)).join(","))
+            },
+        }
+    }
+}
+
+fn json_string_literal(s: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"'  => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\t' => literal.push_str("\\t"),
+            c if c.is_control() => literal.push_str(&format!("\\u{:04x}", c as u32)),
+            c    => literal.push(c),
         }
     }
+    literal.push('"');
+    literal
+}
+
+impl From<&Packet> for Json {
+    fn from(packet: &Packet) -> Self {
+        match packet {
+            Packet::Number(n)     => Json::Integer(*n as i64),
+            Packet::List(packets) => Json::Array(packets.iter().map(Json::from).collect()),
+        }
+    }
+}
+
+impl TryFrom<&Json> for Packet {
+    type Error = PacketError;
+
+    fn try_from(json: &Json) -> Result<Self, Self::Error> {
+        match json {
+            Json::Integer(n) => isize::try_from(*n).map(Packet::Number).map_err(|_| PacketError::UnsupportedJson(json.to_string())),
+            Json::Float(x) if x.fract() == 0.0 && (isize::MIN as f64..isize::MAX as f64).contains(x) => Ok(Packet::Number(*x as isize)),
+            Json::Array(values) => values.iter().map(Packet::try_from).collect::<Result<_, _>>().map(Packet::List),
+            _ => Err(PacketError::UnsupportedJson(json.to_string())),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum PacketError {
+    Syntax { position: usize },
+    TrailingInput { position: usize },
+    UnsupportedJson(String),
+}
+
+impl fmt::Display for PacketError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PacketError::Syntax { position }        => write!(f, "invalid packet syntax at position {}", position),
+            PacketError::TrailingInput { position } => write!(f, "unexpected input after packet at position {}", position),
+            PacketError::UnsupportedJson(json)      => write!(f, "`{}` cannot be represented as a packet", json),
+        }
+    }
+}
+
+fn parse_complete<'a, O, F: FnMut(&'a str) -> IResult<&'a str, O>>(s: &'a str, mut parser: F) -> Result<O, PacketError> {
+    match parser(s) {
+        Ok(("", parsed))   => Ok(parsed),
+        Ok((remaining, _)) => Err(PacketError::TrailingInput { position: s.len() - remaining.len() }),
+        Err(nom::Err::Error(err) | nom::Err::Failure(err)) => Err(PacketError::Syntax { position: s.len() - err.input.len() }),
+        Err(nom::Err::Incomplete(_)) => Err(PacketError::Syntax { position: s.len() }),
+    }
+}
+
+impl FromStr for Packet {
+    type Err = PacketError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_complete(s, packet)
+    }
+}
+
+impl FromStr for Json {
+    type Err = PacketError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_complete(s, json_value)
+    }
 }
 
 // BEGIN NOM PARSING CODE
 
-fn number(input: &str) -> IResult<&str, usize> {
-    map_res(recognize(digit1), str::parse)(input)
+fn ws<'a, O, E: ParseError<&'a str>, F: Parser<&'a str, O, E>>(f: F) -> impl FnMut(&'a str) -> IResult<&'a str, O, E> {
+    delimited(multispace0, f, multispace0)
+}
+
+fn number(input: &str) -> IResult<&str, isize> {
+    map_res(recognize(pair(opt(char('-')), digit1)), str::parse)(input)
 }
 
 fn list(input: &str) -> IResult<&str, Vec<Packet>> {
-    delimited(tag("["), separated_list0(tag(","), packet), tag("]"))(input)
+    delimited(char('['), separated_list0(char(','), packet), ws(char(']')))(input)
 }
 
 fn packet(input: &str) -> IResult<&str, Packet> {
-    alt((
+    ws(alt((
         map(number, Packet::Number),
         map(list,   Packet::List),
+    )))(input)
+}
+
+fn json_number(input: &str) -> IResult<&str, Json> {
+    let fraction = pair(char('.'), digit1);
+    let exponent = tuple((one_of("eE"), opt(one_of("+-")), digit1));
+    map_res(recognize(tuple((opt(char('-')), digit1, opt(fraction), opt(exponent)))), |text: &str| {
+        text.parse().map(Json::Integer).or_else(|_| text.parse().map(Json::Float))
+    })(input)
+}
+
+fn utf16_code_unit(input: &str) -> IResult<&str, u16> {
+    map_res(take_while_m_n(4, 4, |c: char| c.is_ascii_hexdigit()), |hex| u16::from_str_radix(hex, 16))(input)
+}
+
+fn unicode_escape(input: &str) -> IResult<&str, char> {
+    alt((
+        map_opt(separated_pair(utf16_code_unit, tag("\\u"), utf16_code_unit), |(high, low)| {
+            if !(0xd800..0xdc00).contains(&high) || !(0xdc00..0xe000).contains(&low) { return None; }
+            char::decode_utf16([high, low]).next()?.ok()
+        }),
+        map_opt(utf16_code_unit, |unit| char::from_u32(unit as u32)),
     ))(input)
 }
 
+fn json_string(input: &str) -> IResult<&str, String> {
+    let escape = alt((
+        value('\\', char('\\')),
+        value('"', char('"')),
+        value('/', char('/')),
+        value('\u{8}', char('b')),
+        value('\u{c}', char('f')),
+        value('\n', char('n')),
+        value('\r', char('r')),
+        value('\t', char('t')),
+        preceded(char('u'), unicode_escape),
+    ));
+    delimited(char('"'), map(opt(escaped_transform(none_of("\\\""), '\\', escape)), Option::unwrap_or_default), char('"'))(input)
+}
+
+fn json_value(input: &str) -> IResult<&str, Json> {
+    ws(alt((
+        value(Json::Null, tag("null")),
+        value(Json::Bool(true), tag("true")),
+        value(Json::Bool(false), tag("false")),
+        json_number,
+        map(json_string, Json::String),
+        map(delimited(char('['), separated_list0(char(','), json_value), ws(char(']'))), Json::Array),
+        map(delimited(char('{'), separated_list0(char(','), separated_pair(ws(json_string), char(':'), json_value)), ws(char('}'))), Json::Object),
+    )))(input)
+}
+
 // END NOM PARSING CODE
 
 fn read_lines() -> impl Iterator<Item = String> {
@@ -61,20 +339,48 @@ fn read_lines() -> impl Iterator<Item = String> {
     BufReader::new(file).lines().map(|l| l.expect("error reading from file"))
 }
 
-fn parse_packet(line: &str) -> Packet {
-    packet(line).expect("failed to parse packet").1
-}
-
 fn main() {
-    let packets: Vec<_> = read_lines().filter(|l| !l.is_empty()).map(|l| parse_packet(&l)).collect();
+    let packets: Vec<Packet> = read_lines().enumerate()
+        .filter(|(_, l)| !l.is_empty())
+        .map(|(idx, l)| l.parse().unwrap_or_else(|err| panic!("line {}: {}", idx + 1, err)))
+        .collect();
 
     let ordered_indices = packets.iter().tuples().enumerate()
         .filter(|(_, (p1, p2))| p1.le(p2))
         .map(|(idx, _)| idx + 1);
     println!("Part 1: {}", ordered_indices.sum::<usize>());
 
-    let dividers = (parse_packet("[[2]]"), parse_packet("[[6]]"));
-    let before_first = packets.iter().filter(|&p| p.lt(&dividers.0)).count();
-    let before_second = packets.iter().filter(|&p| p.lt(&dividers.1)).count();
-    println!("Part 2: {}", (before_first + 1) * (before_second + 2));
+    if env::args().nth(1).as_deref() == Some("explain") {
+        for (idx, (p1, p2)) in packets.iter().tuples().enumerate() {
+            println!("\n== Pair {} ==\n{}", idx + 1, p1.explain(p2).render(p1, p2));
+        }
+    }
+
+    let dividers: [Packet; 2] = ["[[2]]".parse().unwrap(), "[[6]]".parse().unwrap()];
+    let mut decoder_packets = packets;
+    decoder_packets.extend(dividers.iter().cloned());
+    decoder_packets.sort();
+    let decoder_key: usize = dividers.iter().map(|divider| decoder_packets.partition_point(|p| p < divider) + 1).product();
+    println!("Part 2: {}", decoder_key);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_strings_round_trip_with_all_escapes() {
+        let parsed: Json = r#"["\b\f\u00e9\ud83d\ude00\/", "\u0001"]"#.parse().unwrap();
+        assert_eq!(parsed, Json::Array(vec![Json::String("\u{8}\u{c}\u{e9}\u{1f600}/".to_string()), Json::String("\u{1}".to_string())]));
+        assert_eq!(parsed.to_string(), "[\"\\u0008\\u000c\u{e9}\u{1f600}/\",\"\\u0001\"]");
+        assert_eq!(parsed.to_string().parse::<Json>().unwrap(), parsed);
+        assert!(r#"["\ud83d"]"#.parse::<Json>().is_err());
+    }
+
+    #[test]
+    fn floats_outside_isize_are_unsupported() {
+        assert!(matches!(Packet::try_from(&Json::Float(9223372036854775808.0)), Err(PacketError::UnsupportedJson(_))));
+        assert!(matches!(Packet::try_from(&Json::Float(-9223372036854775808.0)), Ok(Packet::Number(isize::MIN))));
+        assert!(matches!(Packet::try_from(&Json::Float(3.0)), Ok(Packet::Number(3))));
+    }
 }
@@ -1,9 +1,12 @@
-use itertools::{iterate, Itertools};
+use itertools::Itertools;
 
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
-type Point = (usize, usize);
+type Point = (isize, isize);
 
 #[derive(Copy, Clone)]
 enum Material {
@@ -12,50 +15,151 @@ enum Material {
     Air,
 }
 
-struct Sandbox {
-    entry_point: Point,
-    has_floor: bool,
-    contents: Vec<Vec<Material>>,
+#[derive(Debug)]
+enum RockError {
+    InvalidNumber(String),
+    MalformedCoordinate(String),
+    NotAligned(Point, Point),
+    UnexpectedCharacter(char),
+}
+
+impl fmt::Display for RockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RockError::InvalidNumber(number) => write!(f, "could not parse `{}` as an integer", number),
+            RockError::MalformedCoordinate(coordinate) => write!(f, "malformed coordinate `{}`", coordinate),
+            RockError::NotAligned(from, to) => write!(f, "{},{} -> {},{} is not horizontal, vertical or diagonal", from.0, from.1, to.0, to.1),
+            RockError::UnexpectedCharacter(c) => write!(f, "unexpected character `{}` in drawing", c),
+        }
+    }
 }
 
-impl Sandbox {
+struct Cave {
+    walls: Vec<Point>,
+    diagonal_links: HashSet<(Point, Point)>,
+}
 
-    fn with_walls(walls: &[Point], has_floor: bool) -> Self {
-        let entry_point = (500, 0);
-        let floor_height = walls.iter().map(|&(_, y)| y).max().unwrap_or(0) + 2;
-        let maximum_width  = entry_point.0 + floor_height;
+impl Cave {
 
-        let mut contents = vec![vec![Material::Air; entry_point.0 + maximum_width]; floor_height];
-        for &(x, y) in walls {
-            contents[y][x] = Material::Rock;
-        }
+    fn from_paths(paths: &[Vec<Point>]) -> Self {
+        let walls = paths.iter().flatten().copied().collect();
+        let diagonal_links = paths.iter()
+            .flat_map(|path| path.iter().tuple_windows())
+            .filter(|(a, b)| a.0 != b.0 && a.1 != b.1)
+            .map(|(&a, &b)| link(a, b))
+            .collect();
+        Self { walls, diagonal_links }
+    }
+
+    fn from_walls(walls: Vec<Point>) -> Self {
+        Self { walls, diagonal_links: HashSet::new() }
+    }
+
+    fn lowest_rock(&self) -> isize {
+        self.walls.iter().map(|&(_, y)| y).max().unwrap_or(0)
+    }
+
+    // A diagonal rock segment is a solid wall, so sand cannot cross it between the two
+    // cells that only touch at their corners
+    fn crosses_diagonal_wall(&self, (x, y): Point, (to_x, to_y): Point) -> bool {
+        x != to_x && self.diagonal_links.contains(&link((to_x, y), (x, to_y)))
+    }
+}
 
-        Self { entry_point, has_floor, contents }
+fn link(a: Point, b: Point) -> (Point, Point) {
+    (a.min(b), a.max(b))
+}
+
+enum DropOutcome {
+    Settled,
+    FellIntoAbyss,
+    SourceBlocked,
+}
+
+struct Sandbox<'a> {
+    cave: &'a Cave,
+    sources: Vec<Point>,
+    fall_paths: Vec<Vec<Point>>,
+    next_source: usize,
+    lowest_rock: isize,
+    has_floor: bool,
+    contents: HashMap<Point, Material>,
+}
+
+impl<'a> Sandbox<'a> {
+
+    fn new(cave: &'a Cave, sources: &[Point], has_floor: bool) -> Self {
+        let contents = cave.walls.iter().map(|&point| (point, Material::Rock)).collect();
+        Self { cave, sources: sources.to_vec(), fall_paths: vec![Vec::new(); sources.len()], next_source: 0, lowest_rock: cave.lowest_rock(), has_floor, contents }
     }
 
-    fn contents_at(&self, point: Point) -> Option<Material> {
-        self.contents.get(point.1).and_then(|row| row.get(point.0).copied())
+    fn is_air(&self, point: Point) -> bool {
+        if self.has_floor && point.1 >= self.lowest_rock + 2 { return false; }
+        matches!(self.contents.get(&point).copied().unwrap_or(Material::Air), Material::Air)
     }
 
     fn point_to_fall_to(&self, (x, y): Point) -> Option<Point> {
-        [(x, y + 1), (x - 1, y + 1), (x + 1, y + 1)].into_iter().find(|&p| matches!(self.contents_at(p), Some(Material::Air)))
+        [(x, y + 1), (x - 1, y + 1), (x + 1, y + 1)].into_iter()
+            .find(|&p| self.is_air(p) && !self.cave.crosses_diagonal_wall((x, y), p))
+    }
+
+    fn drop_sand_from(&mut self, idx: usize) -> DropOutcome {
+        let mut path = std::mem::take(&mut self.fall_paths[idx]);
+        if self.sources.len() > 1 {
+            // Sand from other sources may have landed anywhere along this path
+            if let Some(blocked) = path.iter().position(|&p| !self.is_air(p)) { path.truncate(blocked); }
+        }
+        if path.is_empty() {
+            if !self.is_air(self.sources[idx]) { return DropOutcome::SourceBlocked; }
+            path.push(self.sources[idx]);
+        }
+
+        let outcome = loop {
+            let current = *path.last().unwrap();
+            if !self.has_floor && current.1 > self.lowest_rock { break DropOutcome::FellIntoAbyss; }
+            match self.point_to_fall_to(current) {
+                Some(next) => path.push(next),
+                None       => {
+                    self.contents.insert(path.pop().unwrap(), Material::Sand);
+                    break DropOutcome::Settled;
+                },
+            }
+        };
+
+        self.fall_paths[idx] = path;
+        outcome
     }
 
     fn drop_sand(&mut self) -> bool {
-        if let Some(Material::Air) = self.contents_at(self.entry_point) {
-            let final_destination = iterate(Some(self.entry_point), |x| x.and_then(|x| self.point_to_fall_to(x)))
-                .take_while(|p| p.is_some())
-                .last().unwrap().unwrap();
-            if self.has_floor || final_destination.1 < self.contents.len() - 1 {
-                self.contents[final_destination.1][final_destination.0] = Material::Sand;
-                true
-            } else {
-                false
+        for _ in 0..self.sources.len() {
+            let idx = self.next_source;
+            self.next_source = (self.next_source + 1) % self.sources.len();
+            match self.drop_sand_from(idx) {
+                DropOutcome::Settled       => return true,
+                DropOutcome::FellIntoAbyss => return false,
+                DropOutcome::SourceBlocked => continue,
             }
-        } else {
-            false
         }
+        false
+    }
+}
+
+fn floored_capacity(cave: &Cave, sources: &[Point]) -> usize {
+    let rocks: HashSet<Point> = cave.walls.iter().copied().collect();
+    let floor = cave.lowest_rock() + 2;
+    let top = sources.iter().map(|&(_, y)| y).min().unwrap_or(floor);
+
+    let (mut reachable, mut capacity) = (HashSet::new(), 0);
+    for y in top..floor {
+        reachable = reachable.iter().flat_map(|&x| [(x, x - 1), (x, x), (x, x + 1)])
+            .filter(|&(from, to)| !cave.crosses_diagonal_wall((from, y - 1), (to, y)))
+            .map(|(_, to)| to)
+            .chain(sources.iter().filter(|&&(_, sy)| sy == y).map(|&(sx, _)| sx))
+            .filter(|&x| !rocks.contains(&(x, y)))
+            .collect();
+        capacity += reachable.len();
     }
+    capacity
 }
 
 fn read_lines() -> impl Iterator<Item = String> {
@@ -63,39 +167,135 @@ fn read_lines() -> impl Iterator<Item = String> {
     BufReader::new(file).lines().map(|l| l.expect("error reading from file"))
 }
 
-fn fill_path(points: &[Point]) -> Vec<Point> {
+fn fill_path(points: &[Point]) -> Result<Vec<Point>, RockError> {
     let mut path = vec![points[0]];
-    for &c in &points[1..] { extend_path(&mut path, c); }
-    path
+    for &c in &points[1..] { extend_path(&mut path, c)?; }
+    Ok(path)
 }
 
-fn extend_path(path: &mut Vec<Point>, point: Point) {
+fn extend_path(path: &mut Vec<Point>, point: Point) -> Result<(), RockError> {
     let (old_x, old_y) = *path.last().unwrap();
-    match point {
-        (new_x, new_y) if new_x == old_x && new_y >= old_y => path.extend((old_y + 1..=new_y).map(|y| (old_x, y))),
-        (new_x, new_y) if new_x == old_x && new_y < old_y  => path.extend((new_y..old_y).rev().map(|y| (old_x, y))),
-        (new_x, new_y) if new_y == old_y && new_x >= old_x => path.extend((old_x + 1..=new_x).map(|x| (x, old_y))),
-        (new_x, new_y) if new_y == old_y && new_x < old_x  => path.extend((new_x..old_x).rev().map(|x| (x, old_y))),
-        _                                                  => panic!("Coordinates were not on the same line"),
-    };
+    let (dx, dy) = (point.0 - old_x, point.1 - old_y);
+    if dx != 0 && dy != 0 && dx.abs() != dy.abs() { return Err(RockError::NotAligned((old_x, old_y), point)); }
+    path.extend((1..=dx.abs().max(dy.abs())).map(|step| (old_x + step * dx.signum(), old_y + step * dy.signum())));
+    Ok(())
 }
 
-fn parse_coordinate(s: &str) -> Point {
-    s.split(",").map(|v| str::parse(v).expect("could not parse as integer")).collect_tuple().expect("wrong number of items in a coordinate")
+fn parse_coordinate(s: &str) -> Result<Point, RockError> {
+    let (x, y) = s.split(',').collect_tuple().ok_or_else(|| RockError::MalformedCoordinate(s.to_string()))?;
+    let parse = |v: &str| v.trim().parse().map_err(|_| RockError::InvalidNumber(v.to_string()));
+    Ok((parse(x)?, parse(y)?))
 }
 
-fn parse_path(line: &str) -> Vec<Point> {
-    fill_path(&line.split(" -> ").map(parse_coordinate).collect::<Vec<_>>())
+fn parse_path(line: &str) -> Result<Vec<Point>, RockError> {
+    fill_path(&line.split(" -> ").map(parse_coordinate).collect::<Result<Vec<_>, _>>()?)
+}
+
+fn parse_drawing_row(y: isize, line: &str) -> Result<(Vec<Point>, Vec<Point>), RockError> {
+    let (mut walls, mut sources) = (Vec::new(), Vec::new());
+    for (x, c) in (0..).zip(line.chars()) {
+        match c {
+            '#'       => walls.push((x, y)),
+            '+'       => sources.push((x, y)),
+            '.' | ' ' => {},
+            _         => return Err(RockError::UnexpectedCharacter(c)),
+        }
+    }
+    Ok((walls, sources))
 }
 
 fn main() {
-    let walls: Vec<_> = read_lines().map(|l| parse_path(&l)).flatten().collect();
+    let (cave, sources) = if env::args().nth(1).as_deref() == Some("drawing") {
+        let (walls, sources): (Vec<_>, Vec<_>) = (0..).zip(read_lines())
+            .map(|(y, l)| parse_drawing_row(y, &l).unwrap_or_else(|err| panic!("line {}: {}", y + 1, err)))
+            .unzip();
+        let sources: Vec<_> = sources.into_iter().flatten().collect();
+        if sources.is_empty() { panic!("drawing contains no sand source"); }
+        (Cave::from_walls(walls.into_iter().flatten().collect()), sources)
+    } else {
+        let paths: Vec<_> = read_lines().enumerate()
+            .map(|(idx, l)| parse_path(&l).unwrap_or_else(|err| panic!("line {}: {}", idx + 1, err)))
+            .collect();
+        (Cave::from_paths(&paths), vec![(500, 0)])
+    };
 
-    let (mut sandbox, mut grains) = (Sandbox::with_walls(&walls, false), 0);
-    while let true = sandbox.drop_sand() { grains += 1; }
+    let (mut sandbox, mut grains) = (Sandbox::new(&cave, &sources, false), 0);
+    while sandbox.drop_sand() { grains += 1; }
     println!("Part 1: {}", grains);
 
-    let (mut sandbox, mut grains) = (Sandbox::with_walls(&walls, true), 0);
-    while let true = sandbox.drop_sand() { grains += 1; }
-    println!("Part 2: {}", grains);
+    println!("Part 2: {}", floored_capacity(&cave, &sources));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use itertools::iterate;
+
+    fn cave(lines: &[&str]) -> Cave {
+        Cave::from_paths(&lines.iter().map(|l| parse_path(l).unwrap()).collect::<Vec<_>>())
+    }
+
+    fn settle<'a>(cave: &'a Cave, sources: &[Point], has_floor: bool) -> Sandbox<'a> {
+        let mut sandbox = Sandbox::new(cave, sources, has_floor);
+        while sandbox.drop_sand() {}
+        sandbox
+    }
+
+    fn grains(sandbox: &Sandbox) -> usize {
+        sandbox.contents.values().filter(|m| matches!(m, Material::Sand)).count()
+    }
+
+    // The original grid simulation: every grain restarts at the source and falls until it
+    // rests, or reaches the row above the floor
+    fn baseline_count(walls: &[Point], source: Point, has_floor: bool) -> usize {
+        let floor_height = walls.iter().map(|&(_, y)| y).max().unwrap_or(0) + 2;
+        let mut blocked: HashSet<Point> = walls.iter().copied().collect();
+        let mut grains = 0;
+        while !blocked.contains(&source) {
+            let is_air = |(x, y): Point| y < floor_height && !blocked.contains(&(x, y));
+            let final_destination = iterate(Some(source), |p| p.and_then(|(x, y)| {
+                [(x, y + 1), (x - 1, y + 1), (x + 1, y + 1)].into_iter().find(|&q| is_air(q))
+            })).take_while(|p| p.is_some()).last().unwrap().unwrap();
+            if !has_floor && final_destination.1 == floor_height - 1 { break; }
+            blocked.insert(final_destination);
+            grains += 1;
+        }
+        grains
+    }
+
+    #[test]
+    fn path_stack_and_flood_fill_match_step_by_step() {
+        let cave = cave(&["498,4 -> 498,6 -> 496,6", "503,4 -> 502,4 -> 502,9 -> 494,9"]);
+        assert_eq!(grains(&settle(&cave, &[(500, 0)], false)), 24);
+        assert_eq!(grains(&settle(&cave, &[(500, 0)], true)), 93);
+        assert_eq!(baseline_count(&cave.walls, (500, 0), false), 24);
+        assert_eq!(baseline_count(&cave.walls, (500, 0), true), 93);
+
+        for sources in [vec![(500, 0)], vec![(490, 0), (505, 2)], vec![(500, -3), (500, 0), (497, 5)]] {
+            assert_eq!(grains(&settle(&cave, &sources, true)), floored_capacity(&cave, &sources));
+        }
+    }
+
+    #[test]
+    fn sand_slips_between_separate_paths_touching_at_corners() {
+        let cave = cave(&["499,0 -> 499,1", "500,2 -> 500,2", "490,10 -> 510,10"]);
+        for has_floor in [false, true] {
+            assert_eq!(grains(&settle(&cave, &[(500, 0)], has_floor)), baseline_count(&cave.walls, (500, 0), has_floor));
+        }
+        assert_eq!(floored_capacity(&cave, &[(500, 0)]), baseline_count(&cave.walls, (500, 0), true));
+        assert_eq!(baseline_count(&cave.walls, (500, 0), false), 90);
+    }
+
+    #[test]
+    fn diagonal_cup_holds_sand() {
+        let cave = cave(&["496,2 -> 500,6 -> 504,2"]);
+        let sandbox = settle(&cave, &[(500, -10)], false);
+        let sand: HashSet<Point> = sandbox.contents.iter().filter(|(_, m)| matches!(m, Material::Sand)).map(|(&p, _)| p).collect();
+        assert!((2..6).all(|y| (501 + y - 6..500 + 6 - y).all(|x| sand.contains(&(x, y)))));
+        assert!(sand.iter().all(|&(_, y)| y < 6));
+
+        let floored = settle(&cave, &[(500, 0)], true);
+        assert_eq!(grains(&floored), floored_capacity(&cave, &[(500, 0)]));
+    }
 }